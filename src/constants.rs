@@ -1,2 +1,5 @@
 pub const VERSION: u8 = 4;
 pub const TRACK_INFO_VERSIONED: u32 = 1;
+// only native libraries are loaded from here, wasm modules are skipped
+pub const PLUGINS_DIRECTORY: &str = "./plugins";
+pub const PLUGIN_ABI_VERSION: u32 = 1;
@@ -1,6 +1,7 @@
 #![recursion_limit = "256"]
 
 use crate::ws::client::WebsocketClient;
+use arc_swap::ArcSwap;
 use axum::{
     Router,
     extract::ws::{Message, Utf8Bytes},
@@ -9,32 +10,36 @@ use axum::{
 };
 use bytesize::ByteSize;
 use cap::Cap;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use dlmalloc::GlobalDlmalloc;
 use dotenv::dotenv;
+use middlewares::ratelimit::{self, RateLimiter};
 use models::{ApiCpu, ApiMemory, ApiNodeMessage, ApiStats};
+use plugins::{Plugin, load_plugins};
 use reqwest::{Client, ClientBuilder};
 use songbird::{driver::Scheduler, id::UserId};
-use source::{deezer::source::Deezer, http::Http, youtube::Youtube};
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use std::{env::set_var, net::SocketAddr};
 use tokio::{
     main, net,
     task::JoinSet,
-    time::{Duration, Instant, interval},
+    time::{Duration, Instant, sleep},
 };
 use tower::ServiceBuilder;
-use tracing::Level;
-use tracing_subscriber::fmt;
 use util::{
     config::Config,
     headers::generate_headers,
-    source::{Source, Sources},
+    logger, metrics,
+    reload::reload,
+    source::{Source, SourceState, sync_sources},
+    tenant::{Tenant, sync_tenants, tenant_usage},
 };
+use voice::frames::collect_frame_stats;
 
 mod constants;
 mod middlewares;
 mod models;
+mod plugins;
 mod routes;
 mod source;
 mod util;
@@ -44,13 +49,26 @@ mod ws;
 #[global_allocator]
 static ALLOCATOR: Cap<GlobalDlmalloc> = Cap::new(GlobalDlmalloc, usize::MAX);
 #[allow(non_upper_case_globals)]
-pub static Config: LazyLock<Config> = LazyLock::new(Config::new);
+pub static Config: LazyLock<ArcSwap<Config>> =
+    LazyLock::new(|| ArcSwap::from_pointee(Config::new()));
 #[allow(non_upper_case_globals)]
 pub static Scheduler: LazyLock<Scheduler> = LazyLock::new(Scheduler::default);
 #[allow(non_upper_case_globals)]
-pub static Clients: LazyLock<DashMap<UserId, WebsocketClient>> = LazyLock::new(DashMap::new);
+pub static Clients: LazyLock<DashMap<u128, WebsocketClient>> = LazyLock::new(DashMap::new);
 #[allow(non_upper_case_globals)]
-pub static AvailableSources: LazyLock<DashMap<String, Sources>> = LazyLock::new(DashMap::new);
+pub static UserSessions: LazyLock<DashMap<UserId, DashSet<u128>>> = LazyLock::new(DashMap::new);
+#[allow(non_upper_case_globals)]
+pub static Tenants: LazyLock<DashMap<String, Arc<Tenant>>> = LazyLock::new(DashMap::new);
+#[allow(non_upper_case_globals)]
+pub static AvailableSources: LazyLock<DashMap<String, Arc<dyn Source>>> =
+    LazyLock::new(DashMap::new);
+#[allow(non_upper_case_globals)]
+pub static SourceStates: LazyLock<DashMap<String, SourceState>> = LazyLock::new(DashMap::new);
+#[allow(non_upper_case_globals)]
+pub static SearchOrder: LazyLock<ArcSwap<Vec<String>>> =
+    LazyLock::new(|| ArcSwap::from_pointee(Vec::new()));
+#[allow(non_upper_case_globals)]
+pub static Plugins: LazyLock<DashMap<String, Arc<Plugin>>> = LazyLock::new(DashMap::new);
 #[allow(non_upper_case_globals)]
 pub static Start: LazyLock<Instant> = LazyLock::new(Instant::now);
 #[allow(non_upper_case_globals)]
@@ -65,67 +83,64 @@ async fn main() {
 
     dotenv().ok();
 
-    let subscriber = fmt()
-        .pretty()
-        .with_file(true)
-        .with_line_number(true)
-        .with_thread_ids(true)
-        .with_thread_names(true)
-        .with_target(true)
-        .with_max_level(Level::DEBUG)
-        .finish();
+    LazyLock::force(&Config);
 
-    tracing::subscriber::set_global_default(subscriber).expect("Failed to set global logger");
+    let _guard = logger::init(Config.load().logging_config.as_ref());
 
-    LazyLock::force(&Config);
     LazyLock::force(&Clients);
+    LazyLock::force(&UserSessions);
+    LazyLock::force(&Tenants);
     LazyLock::force(&AvailableSources);
+    LazyLock::force(&SourceStates);
+    LazyLock::force(&SearchOrder);
+    LazyLock::force(&Plugins);
     LazyLock::force(&Start);
     LazyLock::force(&Reqwest);
 
-    if Config.youtube_config.is_some() {
-        let src_name = String::from("Youtube");
+    sync_tenants(&Config.load_full());
 
-        AvailableSources.insert(
-            src_name.to_lowercase(),
-            Sources::Youtube(Youtube::new(Some(Reqwest.clone()))),
-        );
+    sync_sources(None, &Config.load_full()).await;
 
-        tracing::info!("Registered [{}] into sources list", src_name);
-    }
+    load_plugins();
 
-    if Config.deezer_config.is_some() {
-        let src_name = String::from("Deezer");
-        let client = Deezer::new(Some(Reqwest.clone()));
+    let mut stat = perf_monitor::cpu::ProcessStat::cur().unwrap();
+    let cores = perf_monitor::cpu::processor_numbers().unwrap();
 
-        client.init().await;
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{SignalKind, signal};
 
-        AvailableSources.insert(src_name.to_lowercase(), Sources::Deezer(client));
+        let Ok(mut hangup) = signal(SignalKind::hangup()) else {
+            tracing::warn!(
+                "Failed to listen for SIGHUP, config can only be reloaded via the admin endpoint"
+            );
+            return;
+        };
 
-        tracing::info!("Registered [{}] into sources list", src_name);
-    }
+        while hangup.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading config");
 
-    if Config.http_config.is_some() {
-        let src_name = String::from("HTTP");
+            if let Err(error) = reload().await {
+                tracing::warn!("Failed to reload config due to: {}", error);
+            }
+        }
+    });
 
-        AvailableSources.insert(
-            src_name.to_lowercase(),
-            Sources::Http(Http::new(Some(Reqwest.clone()))),
-        );
+    tokio::spawn(async move {
+        let mut sampled_at = Instant::now();
 
-        tracing::info!("Registered [{}] into sources list", src_name);
-    }
+        loop {
+            // read on every tick so reloads can change it
+            sleep(Duration::from_secs(
+                Config.load().status_update_secs.unwrap_or(30) as u64,
+            ))
+            .await;
 
-    let mut stat = perf_monitor::cpu::ProcessStat::cur().unwrap();
-    let cores = perf_monitor::cpu::processor_numbers().unwrap();
+            let frame_stats = collect_frame_stats(sampled_at.elapsed()).await;
 
-    tokio::spawn(async move {
-        let mut interval = interval(Duration::from_secs(
-            Config.status_update_secs.unwrap_or(30) as u64
-        ));
+            sampled_at = Instant::now();
 
-        loop {
-            interval.tick().await;
+            metrics::frame_stats(frame_stats.as_ref());
 
             let Ok(process_memory_info) = perf_monitor::mem::get_process_memory_info() else {
                 continue;
@@ -152,6 +167,19 @@ async fn main() {
                     .si(),
             );
 
+            for tenant in tenant_usage() {
+                tracing::debug!(
+                    "Tenant Usage: [Tenant: {}] [Sessions: {}] [Players: {}/{}]",
+                    tenant.name,
+                    tenant.sessions,
+                    tenant.players,
+                    tenant
+                        .max_players
+                        .map(|max| max.to_string())
+                        .unwrap_or(String::from("Unlimited"))
+                );
+            }
+
             let stats = ApiStats {
                 players: Scheduler.total_tasks() as u32,
                 playing_players: Scheduler.live_tasks() as u32,
@@ -169,7 +197,7 @@ async fn main() {
                     system_load: usage,
                     lavalink_load: usage,
                 },
-                frame_stats: None,
+                frame_stats,
             };
 
             let serialized =
@@ -189,39 +217,98 @@ async fn main() {
         }
     });
 
+    let config = Config.load_full();
+    let limits = config.rate_limit_config.as_ref();
+    let load_limiter = limits
+        .and_then(|config| config.load.as_ref())
+        .map(|config| RateLimiter::new("load", config));
+    let player_limiter = limits
+        .and_then(|config| config.player.as_ref())
+        .map(|config| RateLimiter::new("player", config));
+    let decode_limiter = limits
+        .and_then(|config| config.decode.as_ref())
+        .map(|config| RateLimiter::new("decode", config));
+
     let app = Router::new()
         .route("/v{version}/websocket", routing::any(routes::global::ws))
         .route(
             "/v{version}/decodetrack",
-            routing::get(routes::endpoints::decode),
+            ratelimit::apply(
+                routing::get(routes::endpoints::decode),
+                decode_limiter.as_ref(),
+            ),
         )
         .route(
             "/v{version}/loadtracks",
-            routing::get(routes::endpoints::encode),
+            ratelimit::apply(
+                routing::get(routes::endpoints::encode),
+                load_limiter.as_ref(),
+            ),
         )
         .route(
-            "/v{version}/sessions/{session_id}/players/{guild_id}",
-            routing::get(routes::endpoints::get_player),
+            "/v{version}/loadsearch",
+            ratelimit::apply(
+                routing::get(routes::endpoints::load_search),
+                load_limiter.as_ref(),
+            ),
         )
         .route(
             "/v{version}/sessions/{session_id}/players/{guild_id}",
-            routing::patch(routes::endpoints::update_player),
+            ratelimit::apply(
+                routing::get(routes::endpoints::get_player)
+                    .patch(routes::endpoints::update_player)
+                    .delete(routes::endpoints::destroy_player),
+                player_limiter.as_ref(),
+            ),
         )
         .route(
-            "/v{version}/sessions/{session_id}/players/{guild_id}",
-            routing::delete(routes::endpoints::destroy_player),
+            "/v{version}/sessions/{session_id}",
+            ratelimit::apply(
+                routing::patch(routes::endpoints::update_session),
+                player_limiter.as_ref(),
+            ),
         )
+        .route("/v{version}/info", routing::get(routes::global::info))
         .route(
-            "/v{version}/sessions/{session_id}",
-            routing::patch(routes::endpoints::update_session),
+            "/v{version}/plugins/{plugin}/{*path}",
+            routing::any(routes::plugins::route),
+        )
+        .route(
+            "/v{version}/admin/reload",
+            routing::post(routes::admin::reload).route_layer(from_fn(middlewares::admin::check)),
+        )
+        .route(
+            "/v{version}/admin/sources",
+            routing::get(routes::admin::get_sources)
+                .patch(routes::admin::update_sources)
+                .route_layer(from_fn(middlewares::admin::check)),
+        )
+        .route(
+            "/v{version}/admin/tenants",
+            routing::get(routes::admin::get_tenants)
+                .route_layer(from_fn(middlewares::admin::check)),
+        )
+        .route(
+            "/v{version}/admin/sources/{name}",
+            routing::get(routes::admin::get_source)
+                .patch(routes::admin::update_source)
+                .route_layer(from_fn(middlewares::admin::check)),
         )
         .route_layer(ServiceBuilder::new().layer(from_fn(middlewares::version::check)))
         .route("/version", routing::get(routes::global::version))
         .route("/", routing::get(routes::global::landing))
-        .layer(from_fn(middlewares::auth::authenticate))
-        .layer(from_fn(middlewares::log::request));
+        .layer(from_fn(middlewares::auth::authenticate));
+
+    // registered outside of the tenant authentication, scrapers use the token from metricsConfig instead
+    #[cfg(feature = "metrics")]
+    let app = app.route(
+        "/metrics",
+        routing::get(routes::global::metrics).route_layer(from_fn(middlewares::metrics::check)),
+    );
+
+    let app = app.layer(from_fn(middlewares::log::request));
 
-    let listener = net::TcpListener::bind(format!("{}:{}", Config.address, Config.port))
+    let listener = net::TcpListener::bind(format!("{}:{}", config.address, config.port))
         .await
         .unwrap();
 
@@ -0,0 +1,19 @@
+use crate::util::{errors::EndpointError, tenant::Tenant};
+use axum::{Extension, body::Body, extract::Request, http::Response, middleware::Next};
+use std::sync::Arc;
+
+pub async fn check(
+    Extension(tenant): Extension<Arc<Tenant>>,
+    request: Request,
+    next: Next,
+) -> Result<Response<Body>, EndpointError> {
+    if !tenant.admin {
+        tracing::warn!(
+            "Rejected a request to an admin route from [Tenant: {}]",
+            tenant.name
+        );
+        return Err(EndpointError::Unauthorized);
+    }
+
+    Ok(next.run(request).await)
+}
@@ -1,17 +1,39 @@
-use crate::Config;
+use crate::Tenants;
 use crate::util::errors::EndpointError;
 use axum::{body::Body, extract::Request, http::Response, middleware::Next};
+use tracing::Instrument;
 
-pub async fn authenticate(request: Request, next: Next) -> Result<Response<Body>, EndpointError> {
+pub async fn authenticate(
+    mut request: Request,
+    next: Next,
+) -> Result<Response<Body>, EndpointError> {
     let authorization = request
         .headers()
         .get("Authorization")
         .ok_or(EndpointError::MissingOption("Authorization"))?
         .to_str()?;
 
-    if authorization != Config.authorization {
-        return Err(EndpointError::Unauthorized);
+    let tenant = Tenants
+        .get(authorization)
+        .map(|tenant| tenant.value().clone())
+        .ok_or(EndpointError::Unauthorized)?;
+
+    if let Some(user_id) = request.headers().get("User-Id") {
+        let user_id = user_id.to_str()?.parse::<u64>()?;
+
+        if !tenant.is_user_allowed(user_id) {
+            tracing::warn!(
+                "Rejected a request from [UserId: {}] as it is not allowed for [Tenant: {}]",
+                user_id,
+                tenant.name
+            );
+            return Err(EndpointError::Unauthorized);
+        }
     }
 
-    Ok(next.run(request).await)
+    let span = tracing::info_span!("tenant", name = %tenant.name);
+
+    request.extensions_mut().insert(tenant);
+
+    Ok(next.run(request).instrument(span).await)
 }
@@ -1,19 +1,27 @@
 use crate::util::errors::EndpointError;
-use axum::{
-    body::Body,
-    extract::{Path, Request},
-    http::Response,
-    middleware::Next,
-};
-use std::collections::HashMap;
+use axum::{body::Body, extract::Request, http::Response, middleware::Next};
+use tracing::Instrument;
+use uuid::Uuid;
 
-#[tracing::instrument]
-pub async fn request(
-    Path(params): Path<HashMap<String, String>>,
-    request: Request,
-    next: Next,
-) -> Result<Response<Body>, EndpointError> {
-    tracing::info!("Received a request! [Endpoint: {}]", request.uri());
+pub async fn request(request: Request, next: Next) -> Result<Response<Body>, EndpointError> {
+    let span = tracing::info_span!(
+        "request",
+        id = %Uuid::now_v7(),
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
 
-    Ok(next.run(request).await)
+    async move {
+        tracing::info!("Received a request! [Endpoint: {}]", request.uri().path());
+
+        // query strings can contain track urls, hence only shown when debugging
+        tracing::debug!(
+            "Request query: {}",
+            request.uri().query().unwrap_or_default()
+        );
+
+        Ok(next.run(request).await)
+    }
+    .instrument(span)
+    .await
 }
@@ -0,0 +1,73 @@
+use crate::util::errors::EndpointError;
+use crate::{Config, Tenants};
+use axum::{body::Body, extract::Request, http::Response, middleware::Next};
+
+/**
+ * Metrics cover every tenant, so they need an admin key or the scrape token from `metricsConfig` sent as a bearer token
+ */
+pub async fn check(request: Request, next: Next) -> Result<Response<Body>, EndpointError> {
+    let authorization = request
+        .headers()
+        .get("Authorization")
+        .ok_or(EndpointError::MissingOption("Authorization"))?
+        .to_str()?;
+
+    let config = Config.load();
+    let token = config
+        .metrics_config
+        .as_ref()
+        .map(|config| config.token.as_str());
+
+    if !is_allowed(authorization, token) {
+        tracing::warn!("Rejected a request to the metrics route");
+        return Err(EndpointError::Unauthorized);
+    }
+
+    Ok(next.run(request).await)
+}
+
+fn is_allowed(authorization: &str, token: Option<&str>) -> bool {
+    if token.is_some_and(|token| authorization.strip_prefix("Bearer ") == Some(token)) {
+        return true;
+    }
+
+    Tenants
+        .get(authorization)
+        .is_some_and(|tenant| tenant.admin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{config::ApiKeyConfig, tenant::Tenant};
+    use std::sync::Arc;
+
+    #[test]
+    fn needs_an_admin_key_or_the_token() {
+        Tenants.insert(
+            String::from("metrics-admin"),
+            Arc::new(Tenant::unrestricted()),
+        );
+        Tenants.insert(
+            String::from("metrics-tenant"),
+            Arc::new(Tenant::new(&ApiKeyConfig {
+                name: String::from("team-a"),
+                key: String::from("metrics-tenant"),
+                allowed_user_ids: None,
+                max_players: None,
+                sources: None,
+            })),
+        );
+
+        assert!(is_allowed("metrics-admin", None));
+        assert!(is_allowed("metrics-admin", Some("scrape")));
+        assert!(is_allowed("Bearer scrape", Some("scrape")));
+
+        // tenant keys would leak the other tenants' counts
+        assert!(!is_allowed("metrics-tenant", Some("scrape")));
+        assert!(!is_allowed("Bearer scrape", None));
+        assert!(!is_allowed("Bearer other", Some("scrape")));
+        assert!(!is_allowed("scrape", Some("scrape")));
+        assert!(!is_allowed("", None));
+    }
+}
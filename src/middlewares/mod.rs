@@ -1,3 +1,7 @@
+pub mod admin;
 pub mod auth;
 pub mod log;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod ratelimit;
 pub mod version;
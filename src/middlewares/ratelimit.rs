@@ -0,0 +1,305 @@
+use crate::Clients;
+use crate::util::{config::RateLimitBucketConfig, errors::EndpointError, tenant::Tenant};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Path, Request, State},
+    http::Response,
+    middleware::{Next, from_fn_with_state},
+    routing::MethodRouter,
+};
+use dashmap::DashMap;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Weak},
+};
+use tokio::time::{Duration, Instant, interval};
+
+static PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/**
+ * Token bucket limiter shared by a group of routes, buckets are keyed by session or remote address
+ */
+pub struct RateLimiter {
+    name: &'static str,
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: DashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(name: &'static str, config: &RateLimitBucketConfig) -> Arc<Self> {
+        let limiter = Arc::new(Self {
+            name,
+            capacity: config.capacity.max(1) as f64,
+            refill_per_sec: config.refill_per_sec.max(f64::MIN_POSITIVE),
+            buckets: DashMap::new(),
+        });
+
+        let weak = Arc::downgrade(&limiter);
+
+        tokio::spawn(async move {
+            let mut interval = interval(PRUNE_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let Some(limiter) = Weak::upgrade(&weak) else {
+                    break;
+                };
+
+                limiter.prune();
+            }
+        });
+
+        tracing::info!(
+            "Rate limiter [{}] is enabled. [Capacity: {}] [Refill: {}/s]",
+            name,
+            limiter.capacity,
+            limiter.refill_per_sec
+        );
+
+        limiter
+    }
+
+    /**
+     * Takes a token from the bucket of this key, or returns how long to wait until one is available
+     */
+    pub fn acquire(&self, key: String) -> Result<(), Duration> {
+        let now = Instant::now();
+
+        let mut bucket = self.buckets.entry(key).or_insert_with(|| Bucket {
+            tokens: self.capacity,
+            updated_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / self.refill_per_sec,
+        ))
+    }
+
+    /**
+     * Drops buckets that already refilled completely since they behave the same as new ones
+     */
+    fn prune(&self) {
+        let now = Instant::now();
+
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+            bucket.tokens + elapsed * self.refill_per_sec < self.capacity
+        });
+    }
+}
+
+/**
+ * Requests are keyed by their session when it exists and belongs to the caller, so rotating made up ids in the path doesn't get fresh buckets
+ */
+fn bucket_key(params: &HashMap<String, String>, addr: &SocketAddr, request: &Request) -> String {
+    let tenant = request.extensions().get::<Arc<Tenant>>();
+
+    params
+        .get("session_id")
+        .and_then(|session_id| session_id.parse::<u128>().ok())
+        .filter(|session_id| {
+            Clients
+                .get(session_id)
+                .is_some_and(|client| tenant.is_none_or(|tenant| client.tenant.owns(tenant)))
+        })
+        .map(|session_id| format!("session:{session_id}"))
+        .unwrap_or_else(|| format!("address:{}", addr.ip()))
+}
+
+pub async fn limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    Path(params): Path<HashMap<String, String>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response<Body>, EndpointError> {
+    let key = bucket_key(&params, &addr, &request);
+
+    if let Err(retry_after) = limiter.acquire(key) {
+        tracing::debug!(
+            "Rate limiter [{}] rejected a request to [Endpoint: {}]",
+            limiter.name,
+            request.uri()
+        );
+
+        return Err(EndpointError::TooManyRequests(
+            retry_after.as_secs_f64().ceil().max(1.0) as u64,
+        ));
+    }
+
+    Ok(next.run(request).await)
+}
+
+pub fn apply(route: MethodRouter, limiter: Option<&Arc<RateLimiter>>) -> MethodRouter {
+    match limiter {
+        Some(limiter) => route.route_layer(from_fn_with_state(limiter.clone(), limit)),
+        None => route,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Clients, ws::client::WebsocketClient};
+    use axum::{
+        Router,
+        http::{StatusCode, header::RETRY_AFTER},
+        routing,
+    };
+    use songbird::id::UserId;
+    use std::num::NonZeroU64;
+    use tower::ServiceExt;
+
+    fn router(capacity: u32) -> Router {
+        let limiter = RateLimiter::new(
+            "test",
+            &RateLimitBucketConfig {
+                capacity,
+                refill_per_sec: 0.001,
+            },
+        );
+
+        Router::new()
+            .route(
+                "/v{version}/sessions/{session_id}",
+                apply(routing::get(|| async { "ok" }), Some(&limiter)),
+            )
+            .route(
+                "/v{version}/loadtracks",
+                apply(routing::get(|| async { "ok" }), Some(&limiter)),
+            )
+    }
+
+    async fn send(router: &Router, uri: &str, ip: [u8; 4]) -> Response<Body> {
+        let mut request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 4000))));
+
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    fn session() -> u128 {
+        let client = WebsocketClient::new(
+            UserId::from(NonZeroU64::MIN),
+            Arc::new(Tenant::unrestricted()),
+        );
+        let session_id = client.session_id;
+
+        Clients.insert(session_id, client);
+
+        session_id
+    }
+
+    #[tokio::test]
+    async fn rejects_with_retry_after_once_exhausted() {
+        let router = router(2);
+
+        for _ in 0..2 {
+            assert_eq!(
+                send(&router, "/v4/loadtracks", [10, 0, 0, 1])
+                    .await
+                    .status(),
+                StatusCode::OK
+            );
+        }
+
+        let response = send(&router, "/v4/loadtracks", [10, 0, 0, 1]).await;
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let retry_after = response.headers()[RETRY_AFTER].to_str().unwrap();
+
+        assert!(retry_after.parse::<u64>().unwrap() >= 1);
+    }
+
+    #[tokio::test]
+    async fn buckets_are_independent_per_address() {
+        let router = router(1);
+
+        assert_eq!(
+            send(&router, "/v4/loadtracks", [10, 0, 0, 2])
+                .await
+                .status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&router, "/v4/loadtracks", [10, 0, 0, 2])
+                .await
+                .status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            send(&router, "/v4/loadtracks", [10, 0, 0, 3])
+                .await
+                .status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn buckets_are_independent_per_session() {
+        let router = router(1);
+        let first = session();
+        let second = session();
+
+        let first = format!("/v4/sessions/{first}");
+        let second = format!("/v4/sessions/{second}");
+
+        assert_eq!(
+            send(&router, &first, [10, 0, 0, 4]).await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&router, &first, [10, 0, 0, 4]).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            send(&router, &second, [10, 0, 0, 4]).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_sessions_share_the_address_bucket() {
+        let router = router(1);
+
+        assert_eq!(
+            send(&router, "/v4/sessions/1", [10, 0, 0, 5])
+                .await
+                .status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&router, "/v4/sessions/2", [10, 0, 0, 5])
+                .await
+                .status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            send(&router, "/v4/sessions/not-a-session", [10, 0, 0, 5])
+                .await
+                .status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+}
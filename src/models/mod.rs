@@ -1,5 +1,5 @@
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 // Lavalink Types: reduced to what we actually need
 
@@ -36,16 +36,6 @@ pub enum Severity {
     Fault,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum LoadType {
-    Track,
-    Playlist,
-    Search,
-    Empty,
-    Error,
-}
-
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 #[serde(tag = "loadType", content = "data")]
@@ -60,6 +50,11 @@ pub enum ApiTrackResult {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Empty;
 
+/**
+ * Extra data a source attaches to tracks and playlists, listed per plugin in `/v4/info`
+ */
+pub type PluginInfo = Map<String, Value>;
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiPlaylistInfo {
@@ -71,10 +66,37 @@ pub struct ApiPlaylistInfo {
 #[serde(rename_all = "camelCase")]
 pub struct ApiTrackPlaylist {
     pub info: ApiPlaylistInfo,
-    pub plugin_info: Empty,
+    #[serde(default)]
+    pub plugin_info: PluginInfo,
     pub tracks: Vec<ApiTrack>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiSearchType {
+    Track,
+    Album,
+    Artist,
+    Playlist,
+    Text,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApiSearchText {
+    pub text: String,
+    pub plugin: PluginInfo,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ApiSearchResult {
+    pub tracks: Vec<ApiTrack>,
+    pub albums: Vec<ApiTrackPlaylist>,
+    pub artists: Vec<ApiTrackPlaylist>,
+    pub playlists: Vec<ApiTrackPlaylist>,
+    pub texts: Vec<ApiSearchText>,
+    pub plugin: PluginInfo,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ApiTrackLoadException {
     pub message: String,
@@ -134,9 +156,11 @@ pub struct ApiTrackInfo {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiTrack {
+    #[serde(default)]
     pub encoded: String,
     pub info: ApiTrackInfo,
-    pub plugin_info: Empty,
+    #[serde(default)]
+    pub plugin_info: PluginInfo,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -156,13 +180,35 @@ pub struct ApiTrackStart {
     pub track: ApiTrack,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ApiTrackEndReason {
+    Finished,
+    LoadFailed,
+    Stopped,
+    Replaced,
+    Cleanup,
+}
+
+impl ApiTrackEndReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiTrackEndReason::Finished => "finished",
+            ApiTrackEndReason::LoadFailed => "loadFailed",
+            ApiTrackEndReason::Stopped => "stopped",
+            ApiTrackEndReason::Replaced => "replaced",
+            ApiTrackEndReason::Cleanup => "cleanup",
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiTrackEnd {
     #[serde(deserialize_with = "str_to_u64", serialize_with = "u64_to_str")]
     pub guild_id: u64,
     pub track: ApiTrack,
-    pub reason: String,
+    pub reason: ApiTrackEndReason,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -308,3 +354,87 @@ pub struct ApiSessionInfo {
     pub resuming_key: u128,
     pub timeout: u16,
 }
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiReloadReport {
+    pub applied: Vec<String>,
+    pub not_applied: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSourceInfo {
+    pub name: String,
+    pub enabled: bool,
+    pub failed: bool,
+    pub successes: u64,
+    pub failures: u64,
+    pub success_rate: Option<f64>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSourcesInfo {
+    pub sources: Vec<ApiSourceInfo>,
+    pub search_order: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTenantInfo {
+    pub name: String,
+    pub sessions: u32,
+    pub players: u32,
+    pub max_players: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSourceUpdate {
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiSourcesUpdate {
+    pub search_order: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiVersion {
+    pub semver: String,
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub pre_release: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiPluginInfo {
+    pub name: String,
+    pub version: String,
+    pub sources: Vec<String>,
+    pub routes: Vec<String>,
+    pub plugin_info_fields: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiInfo {
+    pub version: ApiVersion,
+    pub source_managers: Vec<String>,
+    pub filters: Vec<String>,
+    pub plugins: Vec<ApiPluginInfo>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiPluginRouteResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub body: Value,
+}
@@ -0,0 +1,386 @@
+//
+// Plugins are dynamic libraries loaded from the plugins directory at startup, wasm modules
+// are not supported and only get a warning.
+// Everything crosses the boundary as nul terminated json strings so the abi stays stable
+// between releases. A plugin exports these symbols
+//
+//   ibuki_plugin_abi_version() -> u32
+//   ibuki_plugin_manifest() -> *mut c_char
+//   ibuki_plugin_call(method: *const c_char, payload: *const c_char) -> *mut c_char
+//   ibuki_plugin_free(ptr: *mut c_char)
+//
+// and answers calls with either {"ok": ...} or {"error": "..."}
+//
+
+use crate::Plugins;
+use crate::constants::{PLUGIN_ABI_VERSION, PLUGINS_DIRECTORY};
+use crate::util::errors::PluginError;
+use crate::util::source::register_source;
+use crate::{AvailableSources, Reqwest};
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use source::PluginSource;
+use std::ffi::{CStr, CString, c_char};
+use std::path::Path;
+use std::sync::Arc;
+
+pub mod source;
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type ManifestFn = unsafe extern "C" fn() -> *mut c_char;
+type CallFn = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut c_char;
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginSourceManifest {
+    pub name: String,
+    #[serde(default)]
+    pub search_prefixes: Vec<String>,
+    #[serde(default)]
+    pub url_patterns: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginRouteManifest {
+    pub method: String,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub sources: Vec<PluginSourceManifest>,
+    #[serde(default)]
+    pub routes: Vec<PluginRouteManifest>,
+    #[serde(default)]
+    pub plugin_info_fields: Vec<String>,
+    #[serde(default)]
+    pub events: bool,
+}
+
+#[derive(Deserialize)]
+struct PluginResponse {
+    ok: Option<Value>,
+    error: Option<String>,
+}
+
+pub struct Plugin {
+    pub manifest: PluginManifest,
+    call: CallFn,
+    free: FreeFn,
+    // dropped last since the function pointers above point into it
+    _library: Library,
+}
+
+impl Plugin {
+    /**
+     * Loading a library runs its initializers, only put trusted plugins in the plugins directory
+     */
+    fn load(path: &Path) -> Result<Self, PluginError> {
+        let library = unsafe { Library::new(path)? };
+
+        let abi_version = unsafe { library.get::<AbiVersionFn>(b"ibuki_plugin_abi_version")? };
+        let manifest = unsafe { library.get::<ManifestFn>(b"ibuki_plugin_manifest")? };
+        let call = *unsafe { library.get::<CallFn>(b"ibuki_plugin_call")? };
+        let free = *unsafe { library.get::<FreeFn>(b"ibuki_plugin_free")? };
+
+        let version = unsafe { abi_version() };
+
+        if version != PLUGIN_ABI_VERSION {
+            return Err(PluginError::AbiMismatch(version, PLUGIN_ABI_VERSION));
+        }
+
+        let manifest = unsafe { take_string(manifest(), free)? };
+        let manifest = serde_json::from_str::<PluginManifest>(&manifest)?;
+
+        Ok(Self {
+            manifest,
+            call,
+            free,
+            _library: library,
+        })
+    }
+
+    /**
+     * Calls into the plugin, this blocks so async code should use call_async
+     */
+    pub fn call(&self, method: &str, payload: &str) -> Result<Value, PluginError> {
+        let method = CString::new(method)?;
+        let payload = CString::new(payload)?;
+
+        let response =
+            unsafe { take_string((self.call)(method.as_ptr(), payload.as_ptr()), self.free)? };
+        let response = serde_json::from_str::<PluginResponse>(&response)?;
+
+        match response.error {
+            Some(error) => Err(PluginError::Call(self.manifest.name.clone(), error)),
+            None => Ok(response.ok.unwrap_or(Value::Null)),
+        }
+    }
+
+    pub async fn call_async(
+        self: &Arc<Self>,
+        method: &'static str,
+        payload: Value,
+    ) -> Result<Value, PluginError> {
+        let plugin = self.clone();
+
+        tokio::task::spawn_blocking(move || plugin.call(method, &payload.to_string())).await?
+    }
+
+    pub fn has_route(&self, method: &str, path: &str) -> bool {
+        self.manifest
+            .routes
+            .iter()
+            .any(|route| route.method.eq_ignore_ascii_case(method) && route.path == path)
+    }
+}
+
+unsafe fn take_string(ptr: *mut c_char, free: FreeFn) -> Result<String, PluginError> {
+    if ptr.is_null() {
+        return Err(PluginError::NullResponse);
+    }
+
+    let string = unsafe { CStr::from_ptr(ptr) }
+        .to_string_lossy()
+        .into_owned();
+
+    unsafe { free(ptr) };
+
+    Ok(string)
+}
+
+/**
+ * Loads every plugin in the plugins directory and registers the sources they provide
+ */
+pub fn load_plugins() {
+    let Ok(entries) = std::fs::read_dir(PLUGINS_DIRECTORY) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension == std::env::consts::DLL_EXTENSION => {}
+            Some("wasm") => {
+                tracing::warn!(
+                    "Skipped [{}], wasm plugins are not supported yet",
+                    path.display()
+                );
+                continue;
+            }
+            _ => continue,
+        }
+
+        let plugin = match Plugin::load(&path) {
+            Ok(plugin) => Arc::new(plugin),
+            Err(error) => {
+                tracing::error!("Failed to load plugin [{}]: {}", path.display(), error);
+                continue;
+            }
+        };
+
+        let name = plugin.manifest.name.clone();
+
+        if Plugins.contains_key(&name) {
+            tracing::warn!(
+                "Skipped [{}], a plugin named [{}] is already loaded",
+                path.display(),
+                name
+            );
+            continue;
+        }
+
+        for manifest in plugin.manifest.sources.iter() {
+            let source_name = manifest.name.to_lowercase();
+
+            if AvailableSources.contains_key(&source_name) {
+                tracing::warn!(
+                    "Plugin [{}] tried to register source [{}] which already exists",
+                    name,
+                    source_name
+                );
+                continue;
+            }
+
+            match PluginSource::new(plugin.clone(), manifest, Some(Reqwest.clone())) {
+                Ok(source) => register_source(Arc::new(source)),
+                Err(error) => tracing::error!(
+                    "Plugin [{}] has an invalid url pattern for [{}]: {}",
+                    name,
+                    source_name,
+                    error
+                ),
+            }
+        }
+
+        tracing::info!(
+            "Loaded plugin [{}] [Version: {}]",
+            name,
+            plugin.manifest.version
+        );
+
+        Plugins.insert(name, plugin);
+    }
+}
+
+/**
+ * Forwards a serialized player event to plugins that asked for them. Fire and forget
+ */
+pub fn dispatch_event(event: &str) {
+    for plugin in Plugins.iter() {
+        if !plugin.manifest.events {
+            continue;
+        }
+
+        let plugin = plugin.value().clone();
+        let event = event.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(error) = plugin.call("event", &event) {
+                tracing::warn!("{}", error);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::LazyLock;
+
+    static FIXTURE: LazyLock<PathBuf> = LazyLock::new(|| build_fixture("fixture", &[]));
+    static MISMATCHED_FIXTURE: LazyLock<PathBuf> =
+        LazyLock::new(|| build_fixture("mismatched", &["--cfg", "abi_mismatch"]));
+
+    fn build_fixture(name: &str, args: &[&str]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("ibuki-plugins-{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join(format!("{name}.{}", std::env::consts::DLL_EXTENSION));
+
+        let status = Command::new(std::env::var("RUSTC").unwrap_or(String::from("rustc")))
+            .args(["--edition", "2024", "--crate-type", "cdylib", "-o"])
+            .arg(&path)
+            .args(args)
+            .arg(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/plugin/plugin.rs"
+            ))
+            .status()
+            .unwrap();
+
+        assert!(status.success(), "Failed to build the plugin fixture");
+
+        path
+    }
+
+    /**
+     * Loads the fixture plugin, shared by the source and route tests
+     */
+    pub(crate) fn fixture() -> Arc<Plugin> {
+        Arc::new(Plugin::load(&FIXTURE).unwrap())
+    }
+
+    #[test]
+    fn loads_the_manifest() {
+        let plugin = fixture();
+
+        assert_eq!(plugin.manifest.name, "fixture");
+        assert_eq!(plugin.manifest.version, "1.2.3");
+        assert_eq!(plugin.manifest.sources[0].name, "Fixture");
+        assert_eq!(plugin.manifest.sources[0].search_prefixes, ["fxsearch:"]);
+        assert_eq!(plugin.manifest.plugin_info_fields, ["mood"]);
+        // fields the plugin left out fall back to their defaults
+        assert!(!plugin.manifest.events);
+    }
+
+    #[test]
+    fn rejects_other_abi_versions() {
+        let result = Plugin::load(&MISMATCHED_FIXTURE);
+
+        assert!(matches!(
+            result,
+            Err(PluginError::AbiMismatch(99, PLUGIN_ABI_VERSION))
+        ));
+    }
+
+    #[test]
+    fn rejects_libraries_without_the_abi() {
+        let result = Plugin::load(Path::new("/nonexistent/plugin.so"));
+
+        assert!(matches!(result, Err(PluginError::Library(_))));
+    }
+
+    #[test]
+    fn unwraps_responses() {
+        let plugin = fixture();
+
+        assert_eq!(
+            plugin.call("echo", r#"{"hello":"world"}"#).unwrap(),
+            serde_json::json!({ "hello": "world" })
+        );
+        assert_eq!(plugin.call("unknown", "{}").unwrap(), Value::Null);
+
+        assert!(matches!(
+            plugin.call("fail", "{}"),
+            Err(PluginError::Call(name, error)) if name == "fixture" && error == "nothing here"
+        ));
+        assert!(matches!(
+            plugin.call("garbage", "{}"),
+            Err(PluginError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            plugin.call("null", "{}"),
+            Err(PluginError::NullResponse)
+        ));
+        assert!(matches!(
+            plugin.call("echo", "{\"nul\":\"\0\"}"),
+            Err(PluginError::Nul(_))
+        ));
+    }
+
+    #[test]
+    fn frees_every_string_it_receives() {
+        // a copy gets its own counter, the shared fixture is used by tests running in parallel
+        let path = FIXTURE.with_file_name(format!("freed.{}", std::env::consts::DLL_EXTENSION));
+
+        std::fs::copy(&*FIXTURE, &path).unwrap();
+
+        let plugin = Plugin::load(&path).unwrap();
+
+        for method in ["echo", "fail", "garbage", "unknown"] {
+            let _ = plugin.call(method, "{}");
+        }
+
+        let live_strings = unsafe {
+            plugin
+                ._library
+                .get::<unsafe extern "C" fn() -> usize>(b"fixture_live_strings")
+                .unwrap()()
+        };
+
+        assert_eq!(live_strings, 0);
+    }
+
+    #[test]
+    fn matches_declared_routes() {
+        let plugin = fixture();
+
+        assert!(plugin.has_route("POST", "/echo"));
+        assert!(plugin.has_route("post", "/echo"));
+        assert!(!plugin.has_route("GET", "/echo"));
+        assert!(!plugin.has_route("POST", "/echo/more"));
+        assert!(!plugin.has_route("POST", "/ECHO"));
+    }
+}
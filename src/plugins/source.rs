@@ -0,0 +1,218 @@
+use super::{Plugin, PluginSourceManifest};
+use crate::{
+    models::{ApiTrack, ApiTrackResult},
+    util::{
+        encoder::encode_base64,
+        errors::ResolverError,
+        seek::SeekableSource,
+        source::{Query, Source},
+    },
+};
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use songbird::{
+    input::{Compose, HttpRequest, Input, LiveInput},
+    tracks::Track,
+};
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+struct PluginStream {
+    url: String,
+}
+
+/**
+ * A source provided by a plugin. Names and prefixes are leaked once on load since plugins live until shutdown
+ */
+pub struct PluginSource {
+    client: Client,
+    plugin: Arc<Plugin>,
+    name: &'static str,
+    search_prefixes: Vec<&'static str>,
+    url_patterns: Vec<Regex>,
+}
+
+#[async_trait]
+impl Source for PluginSource {
+    fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    fn get_client(&self) -> Client {
+        self.client.clone()
+    }
+
+    fn search_prefixes(&self) -> &[&'static str] {
+        &self.search_prefixes
+    }
+
+    fn url_patterns(&self) -> &[Regex] {
+        &self.url_patterns
+    }
+
+    async fn resolve(&self, query: Query) -> Result<ApiTrackResult, ResolverError> {
+        let (kind, query) = match query {
+            Query::Url(url) => ("url", url),
+            Query::Search(search) => ("search", search),
+        };
+
+        let value = self
+            .plugin
+            .call_async(
+                "resolve",
+                json!({ "source": self.name, "type": kind, "query": query }),
+            )
+            .await?;
+
+        let mut result = serde_json::from_value::<ApiTrackResult>(value)?;
+
+        // plugins don't need to know how tracks are encoded
+        match &mut result {
+            ApiTrackResult::Track(track) => self.finalize(track)?,
+            ApiTrackResult::Playlist(playlist) => {
+                for track in playlist.tracks.iter_mut() {
+                    self.finalize(track)?;
+                }
+            }
+            ApiTrackResult::Search(tracks) => {
+                for track in tracks.iter_mut() {
+                    self.finalize(track)?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(result)
+    }
+
+    async fn make_playable(&self, track: ApiTrack) -> Result<Track, ResolverError> {
+        let value = self
+            .plugin
+            .call_async("stream", json!({ "source": self.name, "track": track }))
+            .await?;
+
+        let stream = serde_json::from_value::<PluginStream>(value)?;
+
+        let mut request = HttpRequest::new(self.get_client(), stream.url);
+
+        let stream = request.create_async().await?;
+
+        let seekable = SeekableSource::new(stream.input);
+
+        let input = Input::Live(
+            LiveInput::Raw(seekable.into_audio_stream(stream.hint)),
+            None,
+        );
+
+        Ok(Track::new_with_data(input, Arc::new(track)))
+    }
+}
+
+impl PluginSource {
+    pub fn new(
+        plugin: Arc<Plugin>,
+        manifest: &PluginSourceManifest,
+        client: Option<Client>,
+    ) -> Result<Self, regex::Error> {
+        let url_patterns = manifest
+            .url_patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<Vec<Regex>, regex::Error>>()?;
+
+        Ok(Self {
+            client: client.unwrap_or_default(),
+            plugin,
+            name: Box::leak(manifest.name.to_lowercase().into_boxed_str()),
+            search_prefixes: manifest
+                .search_prefixes
+                .iter()
+                .map(|prefix| &*Box::leak(prefix.clone().into_boxed_str()))
+                .collect(),
+            url_patterns,
+        })
+    }
+
+    fn finalize(&self, track: &mut ApiTrack) -> Result<(), ResolverError> {
+        track.info.source_name = self.name.to_string();
+        track.encoded = encode_base64(&track.info)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{plugins::tests::fixture, util::decoder::decode_base64};
+
+    fn source() -> PluginSource {
+        let plugin = fixture();
+        let manifest = plugin.manifest.sources[0].clone();
+
+        PluginSource::new(plugin, &manifest, None).unwrap()
+    }
+
+    fn assert_finalized(track: &ApiTrack) {
+        assert_eq!(track.info.source_name, "fixture");
+        assert_eq!(decode_base64(&track.encoded).unwrap(), track.info);
+        assert_eq!(track.plugin_info["mood"], "calm");
+    }
+
+    #[test]
+    fn uses_the_manifest() {
+        let source = source();
+
+        assert_eq!(source.get_name(), "fixture");
+        assert_eq!(source.search_prefixes(), ["fxsearch:"]);
+        assert!(matches!(
+            source.parse_query("https://fixture.test/fx-1"),
+            Some(Query::Url(_))
+        ));
+        assert!(matches!(
+            source.parse_query("fxsearch:calm"),
+            Some(Query::Search(_))
+        ));
+        assert!(source.parse_query("https://example.com/fx-1").is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_url_patterns() {
+        let manifest = PluginSourceManifest {
+            name: String::from("Broken"),
+            search_prefixes: Vec::new(),
+            url_patterns: vec![String::from("(unclosed")],
+        };
+
+        assert!(PluginSource::new(fixture(), &manifest, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn encodes_resolved_tracks() {
+        let source = source();
+
+        let ApiTrackResult::Track(track) = source
+            .resolve(Query::Url(String::from("https://fixture.test/fx-1")))
+            .await
+            .unwrap()
+        else {
+            panic!("Expected a track");
+        };
+
+        assert_finalized(&track);
+
+        let ApiTrackResult::Search(tracks) = source
+            .resolve(Query::Search(String::from("fxsearch:calm")))
+            .await
+            .unwrap()
+        else {
+            panic!("Expected search results");
+        };
+
+        assert_eq!(tracks.len(), 2);
+        tracks.iter().for_each(assert_finalized);
+    }
+}
@@ -0,0 +1,98 @@
+use super::SourceMethodsPath;
+use crate::models::{ApiSourceUpdate, ApiSourcesInfo, ApiSourcesUpdate};
+use crate::util::errors::EndpointError;
+use crate::util::reload;
+use crate::util::source::{set_source_enabled, source_info};
+use crate::util::tenant::tenant_usage;
+use crate::{AvailableSources, SearchOrder};
+use axum::extract::Path;
+use axum::{Json, body::Body, response::Response};
+use std::sync::Arc;
+
+pub async fn reload() -> Result<Response<Body>, EndpointError> {
+    let report = reload::reload().await?;
+
+    let string = serde_json::to_string_pretty(&report)?;
+
+    Ok(Response::new(Body::from(string)))
+}
+
+pub async fn get_tenants() -> Result<Response<Body>, EndpointError> {
+    let string = serde_json::to_string_pretty(&tenant_usage())?;
+
+    Ok(Response::new(Body::from(string)))
+}
+
+fn sources_info() -> ApiSourcesInfo {
+    let mut names: Vec<String> = AvailableSources
+        .iter()
+        .map(|source| source.key().clone())
+        .collect();
+
+    names.sort();
+
+    ApiSourcesInfo {
+        sources: names.iter().filter_map(|name| source_info(name)).collect(),
+        search_order: SearchOrder.load().to_vec(),
+    }
+}
+
+pub async fn get_sources() -> Result<Response<Body>, EndpointError> {
+    let string = serde_json::to_string_pretty(&sources_info())?;
+
+    Ok(Response::new(Body::from(string)))
+}
+
+pub async fn update_sources(
+    Json(update): Json<ApiSourcesUpdate>,
+) -> Result<Response<Body>, EndpointError> {
+    let order: Vec<String> = update
+        .search_order
+        .iter()
+        .map(|name| name.to_lowercase())
+        .collect();
+
+    if order
+        .iter()
+        .any(|name| !AvailableSources.contains_key(name))
+    {
+        return Err(EndpointError::UnprocessableEntity(
+            "searchOrder contains a source that is not registered",
+        ));
+    }
+
+    tracing::info!("Search order is now [{}]", order.join(", "));
+
+    SearchOrder.store(Arc::new(order));
+
+    let string = serde_json::to_string_pretty(&sources_info())?;
+
+    Ok(Response::new(Body::from(string)))
+}
+
+pub async fn get_source(
+    Path(SourceMethodsPath { name }): Path<SourceMethodsPath>,
+) -> Result<Response<Body>, EndpointError> {
+    let info = source_info(&name.to_lowercase()).ok_or(EndpointError::NotFound)?;
+
+    let string = serde_json::to_string_pretty(&info)?;
+
+    Ok(Response::new(Body::from(string)))
+}
+
+pub async fn update_source(
+    Path(SourceMethodsPath { name }): Path<SourceMethodsPath>,
+    Json(update): Json<ApiSourceUpdate>,
+) -> Result<Response<Body>, EndpointError> {
+    let name = name.to_lowercase();
+
+    if !set_source_enabled(&name, update.enabled) {
+        return Err(EndpointError::NotFound);
+    }
+
+    let info = source_info(&name).ok_or(EndpointError::NotFound)?;
+
+    let string = serde_json::to_string_pretty(&info)?;
+
+    Ok(Response::new(Body::from(string)))
+}
@@ -1,32 +1,57 @@
 use super::{
-    DecodeQueryString, EncodeQueryString, PlayerMethodsPath, PlayerUpdateQuery, SessionMethodsPath,
+    DecodeQueryString, EncodeQueryString, LoadSearchQueryString, PlayerMethodsPath,
+    PlayerUpdateQuery, SessionMethodsPath,
 };
 use crate::models::{
-    ApiPlayerOptions, ApiSessionBody, ApiSessionInfo, ApiTrack, ApiTrackResult, Empty,
+    ApiPlayerOptions, ApiSearchType, ApiSessionBody, ApiSessionInfo, ApiTrack, ApiTrackResult,
+    PluginInfo,
 };
 use crate::util::converter::numbers::IbukiGuildId;
 use crate::util::decoder::decode_base64;
 use crate::util::errors::EndpointError;
-use crate::util::source::{Source, Sources};
-use crate::{AvailableSources, Clients};
-use axum::Json;
+use crate::util::metrics::observe_resolve;
+use crate::util::source::{find_sources, is_source_enabled, record_result};
+use crate::util::tenant::Tenant;
+use crate::ws::client::WebsocketClient;
+use crate::{AvailableSources, Clients, SearchOrder};
 use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
 use axum::{body::Body, extract::Query, response::Response};
+use dashmap::mapref::one::{Ref, RefMut};
 use serde_json::Value;
 use songbird::id::GuildId;
 use std::num::NonZeroU64;
+use std::sync::Arc;
 use std::sync::atomic::Ordering;
+use tokio::time::Instant;
+
+/**
+ * Sessions are only visible to the tenant that created them
+ */
+fn get_client(session_id: u128, tenant: &Tenant) -> Option<Ref<'static, u128, WebsocketClient>> {
+    Clients
+        .get(&session_id)
+        .filter(|client| client.tenant.owns(tenant))
+}
+
+fn get_client_mut(
+    session_id: u128,
+    tenant: &Tenant,
+) -> Option<RefMut<'static, u128, WebsocketClient>> {
+    Clients
+        .get_mut(&session_id)
+        .filter(|client| client.tenant.owns(tenant))
+}
 
 pub async fn get_player(
     Path(PlayerMethodsPath {
         session_id,
         guild_id,
     }): Path<PlayerMethodsPath>,
+    Extension(tenant): Extension<Arc<Tenant>>,
 ) -> Result<Response<Body>, EndpointError> {
-    let client = Clients
-        .iter()
-        .find(|client| client.session_id == session_id)
-        .ok_or(EndpointError::NotFound)?;
+    let client = get_client(session_id, &tenant).ok_or(EndpointError::NotFound)?;
 
     let id = GuildId::from(NonZeroU64::try_from(IbukiGuildId(guild_id))?);
 
@@ -46,12 +71,10 @@ pub async fn update_player(
         session_id,
         guild_id,
     }): Path<PlayerMethodsPath>,
+    Extension(tenant): Extension<Arc<Tenant>>,
     Json(update_player): Json<ApiPlayerOptions>,
 ) -> Result<Response<Body>, EndpointError> {
-    let client = Clients
-        .iter()
-        .find(|client| client.session_id == session_id)
-        .ok_or(EndpointError::NotFound)?;
+    let client = get_client(session_id, &tenant).ok_or(EndpointError::NotFound)?;
 
     let id = GuildId::from(NonZeroU64::try_from(IbukiGuildId(guild_id))?);
 
@@ -112,11 +135,9 @@ pub async fn destroy_player(
         session_id,
         guild_id,
     }): Path<PlayerMethodsPath>,
+    Extension(tenant): Extension<Arc<Tenant>>,
 ) -> Result<Response<Body>, EndpointError> {
-    let client = Clients
-        .iter()
-        .find(|client| client.session_id == session_id)
-        .ok_or(EndpointError::NotFound)?;
+    let client = get_client(session_id, &tenant).ok_or(EndpointError::NotFound)?;
 
     let id = GuildId::from(NonZeroU64::try_from(IbukiGuildId(guild_id))?);
 
@@ -128,12 +149,10 @@ pub async fn destroy_player(
 #[tracing::instrument]
 pub async fn update_session(
     Path(SessionMethodsPath { session_id }): Path<SessionMethodsPath>,
+    Extension(tenant): Extension<Arc<Tenant>>,
     Json(update_session): Json<ApiSessionBody>,
 ) -> Result<Response<Body>, EndpointError> {
-    let mut client = Clients
-        .iter_mut()
-        .find(|client| client.session_id == session_id)
-        .ok_or(EndpointError::NotFound)?;
+    let mut client = get_client_mut(session_id, &tenant).ok_or(EndpointError::NotFound)?;
 
     client.resume = update_session.resuming;
     client.timeout = update_session.timeout as u16;
@@ -154,7 +173,7 @@ pub async fn decode(query: Query<DecodeQueryString>) -> Result<Response<Body>, E
     let track = ApiTrack {
         encoded: query.track.clone(),
         info: track,
-        plugin_info: Empty,
+        plugin_info: PluginInfo::new(),
     };
 
     let string = serde_json::to_string_pretty(&track)?;
@@ -162,41 +181,111 @@ pub async fn decode(query: Query<DecodeQueryString>) -> Result<Response<Body>, E
     Ok(Response::new(Body::from(string)))
 }
 
-#[tracing::instrument]
-pub async fn encode(query: Query<EncodeQueryString>) -> Result<Response<Body>, EndpointError> {
+/**
+ * Searches like `ytsearch:term` can fall back to another source, this returns the term
+ */
+fn search_term(identifier: &str) -> Option<&str> {
+    let (prefix, term) = identifier.split_once(':')?;
+
+    prefix.ends_with("search").then_some(term)
+}
+
+// identifiers are often track urls, only their prefix is recorded
+#[tracing::instrument(
+    level = "debug",
+    skip(query),
+    fields(prefix = query.identifier.split_once(':').map(|(prefix, _)| prefix))
+)]
+pub async fn encode(
+    query: Query<EncodeQueryString>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+) -> Result<Response<Body>, EndpointError> {
     let track: ApiTrackResult = {
         let mut result = ApiTrackResult::Empty(None);
+        let mut error = None;
+        let mut tried = Vec::new();
 
-        for source in AvailableSources.iter() {
-            match source.value() {
-                Sources::Youtube(src) => {
-                    let option = src.parse_query(&query.identifier);
+        for (source, source_query) in find_sources(&query.identifier) {
+            let name = source.get_name();
 
-                    if let Some(query) = option {
-                        result = src.resolve(query).await?;
-                    }
-                }
-                Sources::Deezer(src) => {
-                    let option = src.parse_query(&query.identifier);
+            if !tenant.is_source_allowed(name) || !is_source_enabled(name) {
+                continue;
+            }
 
-                    if let Some(query) = option {
-                        result = src.resolve(query).await?;
-                    }
-                }
-                Sources::Http(src) => {
-                    let option = src.parse_query(&query.identifier);
+            let started = Instant::now();
 
-                    if let Some(query) = option {
-                        result = src.resolve(query).await?;
-                    }
+            let resolved = source.resolve(source_query).await;
+
+            observe_resolve(name, started.elapsed(), resolved.is_ok());
+            record_result(name, resolved.as_ref().err());
+
+            tried.push(name);
+
+            match resolved {
+                Ok(resolved) => result = resolved,
+                // a generic source like ytdlp may still load it
+                Err(resolver_error) => {
+                    error.get_or_insert(resolver_error);
+                    continue;
                 }
             }
 
             if result != ApiTrackResult::Empty(None) {
+                error = None;
                 break;
             }
         }
 
+        if result == ApiTrackResult::Empty(None) {
+            if let Some(term) = search_term(&query.identifier) {
+                for name in SearchOrder.load().iter() {
+                    if tried.contains(&name.as_str())
+                        || !tenant.is_source_allowed(name)
+                        || !is_source_enabled(name)
+                    {
+                        continue;
+                    }
+
+                    let Some(source) = AvailableSources.get(name).map(|source| source.clone())
+                    else {
+                        continue;
+                    };
+
+                    let Some(source_query) = source
+                        .search_prefix()
+                        .and_then(|prefix| source.parse_query(&format!("{prefix}{term}")))
+                    else {
+                        continue;
+                    };
+
+                    tracing::debug!("Falling back to [{}] for [{}]", name, query.identifier);
+
+                    let started = Instant::now();
+
+                    let resolved = source.resolve(source_query).await;
+
+                    observe_resolve(name, started.elapsed(), resolved.is_ok());
+                    record_result(name, resolved.as_ref().err());
+
+                    match resolved {
+                        Ok(resolved) if resolved != ApiTrackResult::Empty(None) => {
+                            result = resolved;
+                            error = None;
+                            break;
+                        }
+                        Ok(_) => {}
+                        Err(resolver_error) => {
+                            error.get_or_insert(resolver_error);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(error.into());
+        }
+
         result
     };
 
@@ -204,3 +293,49 @@ pub async fn encode(query: Query<EncodeQueryString>) -> Result<Response<Body>, E
 
     Ok(Response::new(Body::from(string)))
 }
+
+#[tracing::instrument(
+    level = "debug",
+    skip(query),
+    fields(prefix = query.query.split_once(':').map(|(prefix, _)| prefix))
+)]
+pub async fn load_search(
+    query: Query<LoadSearchQueryString>,
+    Extension(tenant): Extension<Arc<Tenant>>,
+) -> Result<Response<Body>, EndpointError> {
+    let types = match &query.types {
+        Some(types) => types
+            .split(',')
+            .filter(|kind| !kind.trim().is_empty())
+            .map(|kind| serde_json::from_value::<ApiSearchType>(Value::from(kind.trim())))
+            .collect::<Result<Vec<ApiSearchType>, serde_json::Error>>()
+            .map_err(|_| EndpointError::UnprocessableEntity("types"))?,
+        None => vec![
+            ApiSearchType::Track,
+            ApiSearchType::Album,
+            ApiSearchType::Artist,
+            ApiSearchType::Playlist,
+            ApiSearchType::Text,
+        ],
+    };
+
+    for (source, _) in find_sources(&query.query) {
+        let name = source.get_name();
+
+        if !tenant.is_source_allowed(name) || !is_source_enabled(name) {
+            continue;
+        }
+
+        if let Some(result) = source.load_search(&query.query, &types).await? {
+            let string = serde_json::to_string_pretty(&result)?;
+
+            return Ok(Response::new(Body::from(string)));
+        }
+    }
+
+    let mut reply = Response::new(Body::empty());
+
+    *reply.status_mut() = StatusCode::NO_CONTENT;
+
+    Ok(reply)
+}
@@ -1,24 +1,31 @@
+use crate::constants::VERSION;
+use crate::models::{ApiInfo, ApiPluginInfo, ApiVersion};
 use crate::util::converter::numbers::IbukiUserId;
 use crate::util::errors::EndpointError;
+use crate::util::tenant::Tenant;
 use crate::ws::client::{
     WebsocketRequestData, handle_websocket_upgrade_error, handle_websocket_upgrade_request,
 };
+use crate::{AvailableSources, Plugins};
+use axum::Extension;
 use axum::body::Body;
 use axum::extract::{ConnectInfo, WebSocketUpgrade};
 use axum::http::{HeaderMap, Response};
 use songbird::id::UserId;
 use std::net::SocketAddr;
 use std::num::NonZeroU64;
+use std::sync::Arc;
 
 pub async fn landing() -> String {
     String::from("Hello World")
 }
 
-#[tracing::instrument]
+#[tracing::instrument(skip(websocket_upgrade, headers))]
 pub async fn ws(
     websocket_upgrade: WebSocketUpgrade,
     headers: HeaderMap,
     connection: ConnectInfo<SocketAddr>,
+    Extension(tenant): Extension<Arc<Tenant>>,
 ) -> Result<Response<Body>, EndpointError> {
     let user_agent = headers
         .get("User-Agent")
@@ -31,18 +38,24 @@ pub async fn ws(
         .to_str()?
         .parse::<u64>()?;
 
+    if !tenant.is_user_allowed(user_id) {
+        return Err(EndpointError::Unauthorized);
+    }
+
     let request = WebsocketRequestData {
         user_agent: user_agent.into(),
         user_id: UserId::from(NonZeroU64::try_from(IbukiUserId(user_id))?),
         session_id: headers
             .get("Session-Id")
             .and_then(|data| data.to_str().map_or(None, |data| data.parse::<u128>().ok())),
+        tenant,
     };
 
     tracing::info!(
-        "Received a connection request from {}({})",
+        "Received a connection request from {}({}) [Tenant: {}]",
         user_id,
-        user_agent
+        user_agent,
+        request.tenant.name
     );
 
     // now stop complaining compiler
@@ -63,3 +76,111 @@ pub async fn ws(
 pub async fn version() -> String {
     String::from(env!("CARGO_PKG_VERSION"))
 }
+
+pub async fn info() -> Result<Response<Body>, EndpointError> {
+    let mut source_managers: Vec<String> = AvailableSources
+        .iter()
+        .map(|source| source.key().clone())
+        .collect();
+
+    source_managers.sort();
+
+    let mut plugins: Vec<ApiPluginInfo> = Plugins
+        .iter()
+        .map(|plugin| ApiPluginInfo {
+            name: plugin.manifest.name.clone(),
+            version: plugin.manifest.version.clone(),
+            sources: plugin
+                .manifest
+                .sources
+                .iter()
+                .map(|source| source.name.to_lowercase())
+                .collect(),
+            routes: plugin
+                .manifest
+                .routes
+                .iter()
+                .map(|route| {
+                    format!(
+                        "{} /v{}/plugins/{}{}",
+                        route.method.to_uppercase(),
+                        VERSION,
+                        plugin.manifest.name,
+                        route.path
+                    )
+                })
+                .collect(),
+            plugin_info_fields: plugin.manifest.plugin_info_fields.clone(),
+        })
+        .collect();
+
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let info = ApiInfo {
+        version: ApiVersion {
+            semver: String::from(env!("CARGO_PKG_VERSION")),
+            major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+            minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+            patch: env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0),
+            pre_release: Some(env!("CARGO_PKG_VERSION_PRE"))
+                .filter(|pre| !pre.is_empty())
+                .map(String::from),
+        },
+        source_managers,
+        filters: Vec::new(),
+        plugins,
+    };
+
+    let string = serde_json::to_string_pretty(&info)?;
+
+    Ok(Response::new(Body::from(string)))
+}
+
+#[cfg(feature = "metrics")]
+pub async fn metrics() -> Response<Body> {
+    use axum::http::{StatusCode, header::CONTENT_TYPE};
+    use axum::response::IntoResponse;
+
+    match crate::util::metrics::render() {
+        Ok(text) => ([(CONTENT_TYPE, "text/plain; version=0.0.4")], text).into_response(),
+        Err(error) => {
+            tracing::warn!("Failed to render metrics due to: {:?}", error);
+            (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::tests::fixture;
+
+    #[tokio::test]
+    async fn lists_plugins() {
+        Plugins.insert(String::from("fixture"), fixture());
+
+        let response = info().await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let info = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+
+        let plugin = info["plugins"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|plugin| plugin["name"] == "fixture")
+            .unwrap();
+
+        assert_eq!(
+            *plugin,
+            serde_json::json!({
+                "name": "fixture",
+                "version": "1.2.3",
+                "sources": ["fixture"],
+                "routes": [format!("POST /v{VERSION}/plugins/fixture/echo")],
+                "pluginInfoFields": ["mood"],
+            })
+        );
+    }
+}
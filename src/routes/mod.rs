@@ -1,7 +1,9 @@
 use serde::Deserialize;
 
+pub mod admin;
 pub mod endpoints;
 pub mod global;
+pub mod plugins;
 
 #[derive(Deserialize, Debug)]
 pub struct PlayerMethodsPath {
@@ -14,6 +16,17 @@ pub struct SessionMethodsPath {
     pub session_id: u128,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct PluginRoutePath {
+    pub plugin: String,
+    pub path: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SourceMethodsPath {
+    pub name: String,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerUpdateQuery {
@@ -29,3 +42,9 @@ pub struct DecodeQueryString {
 pub struct EncodeQueryString {
     pub identifier: String,
 }
+
+#[derive(Deserialize, Debug)]
+pub struct LoadSearchQueryString {
+    pub query: String,
+    pub types: Option<String>,
+}
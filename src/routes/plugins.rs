@@ -0,0 +1,127 @@
+use super::PluginRoutePath;
+use crate::Plugins;
+use crate::models::ApiPluginRouteResponse;
+use crate::util::errors::EndpointError;
+use crate::util::tenant::Tenant;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, RawQuery};
+use axum::http::{Method, StatusCode};
+use axum::{Extension, response::Response};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/**
+ * Forwards requests to routes a plugin declared in its manifest
+ */
+pub async fn route(
+    method: Method,
+    Path(PluginRoutePath { plugin, path }): Path<PluginRoutePath>,
+    RawQuery(query): RawQuery,
+    Extension(tenant): Extension<Arc<Tenant>>,
+    body: Bytes,
+) -> Result<Response<Body>, EndpointError> {
+    let plugin = Plugins
+        .get(&plugin)
+        .map(|plugin| plugin.value().clone())
+        .ok_or(EndpointError::NotFound)?;
+
+    let path = format!("/{path}");
+
+    if !plugin.has_route(method.as_str(), &path) {
+        return Err(EndpointError::NotFound);
+    }
+
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice::<Value>(&body)?
+    };
+
+    let value = plugin
+        .call_async(
+            "route",
+            json!({
+                "method": method.as_str(),
+                "path": path,
+                "query": query,
+                "body": body,
+                "tenant": tenant.name,
+            }),
+        )
+        .await?;
+
+    let response = serde_json::from_value::<ApiPluginRouteResponse>(value)?;
+
+    let string = serde_json::to_string_pretty(&response.body)?;
+
+    let mut reply = Response::new(Body::from(string));
+
+    *reply.status_mut() = StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK);
+
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::tests::fixture;
+
+    async fn call(
+        method: Method,
+        path: &str,
+        query: Option<&str>,
+        body: &str,
+    ) -> Result<(StatusCode, Value), EndpointError> {
+        Plugins.insert(String::from("fixture"), fixture());
+
+        let response = route(
+            method,
+            Path(PluginRoutePath {
+                plugin: String::from("fixture"),
+                path: path.to_string(),
+            }),
+            RawQuery(query.map(String::from)),
+            Extension(Arc::new(Tenant::unrestricted())),
+            Bytes::from(body.to_string()),
+        )
+        .await?;
+
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        Ok((status, serde_json::from_slice(&body).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn forwards_declared_routes() {
+        let (status, body) = call(Method::POST, "echo", Some("loud=true"), r#"{"say":"hi"}"#)
+            .await
+            .unwrap();
+
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(
+            body,
+            json!({
+                "received": {
+                    "method": "POST",
+                    "path": "/echo",
+                    "query": "loud=true",
+                    "body": { "say": "hi" },
+                    "tenant": "default",
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_undeclared_routes() {
+        for (method, path) in [(Method::GET, "echo"), (Method::POST, "other")] {
+            assert!(matches!(
+                call(method, path, None, "").await,
+                Err(EndpointError::NotFound)
+            ));
+        }
+    }
+}
@@ -1,6 +1,7 @@
 pub mod model;
 pub mod source;
 pub mod stream;
+pub mod token;
 
 static _SHARE_URL: &str = "https://deezer.page.link/";
 static PUBLIC_API_BASE: &str = "https://api.deezer.com/2.0";
@@ -1,13 +1,17 @@
 #![allow(dead_code, unused)]
 
-use crate::Config;
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DeezerQuality {
+    #[serde(rename = "FLAC")]
     Flac,
+    #[serde(rename = "MP3_320")]
     Mp3_320,
+    #[serde(rename = "MP3_256")]
     Mp3_256,
+    #[serde(rename = "MP3_128")]
     Mp3_128,
 }
 
@@ -299,6 +303,46 @@ pub struct InternalDeezerOptionAudioQualities {
     pub wifi_streaming: Vec<String>,
 }
 
+#[derive(Serialize, Debug)]
+pub struct DeezerTrackMixBody {
+    pub sng_id: String,
+    pub start_with_input_track: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeezerArtistRadioBody {
+    pub art_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InternalDeezerMixData {
+    pub data: Vec<InternalDeezerMixTrack>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InternalDeezerMixTrack {
+    #[serde(rename = "SNG_ID")]
+    pub sng_id: String,
+    #[serde(rename = "SNG_TITLE")]
+    pub sng_title: String,
+    #[serde(rename = "VERSION")]
+    pub version: Option<String>,
+    #[serde(rename = "ART_ID")]
+    pub art_id: String,
+    #[serde(rename = "ART_NAME")]
+    pub art_name: String,
+    #[serde(rename = "ALB_ID")]
+    pub alb_id: String,
+    #[serde(rename = "ALB_TITLE")]
+    pub alb_title: String,
+    #[serde(rename = "ALB_PICTURE")]
+    pub alb_picture: String,
+    #[serde(rename = "DURATION")]
+    pub duration: String,
+    #[serde(rename = "ISRC")]
+    pub isrc: Option<String>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct InternalDeezerResponseError {
     #[serde(rename = "type")]
@@ -317,9 +361,9 @@ pub struct InternalDeezerResponse<T> {
 pub struct DeezerApiArtist {
     pub id: u32,
     pub name: String,
-    pub link: String,
+    pub link: Option<String>,
     #[serde(rename = "picture_medium")]
-    pub thumbnail: String,
+    pub thumbnail: Option<String>,
     #[serde(rename = "tracklist")]
     pub tracks: String,
 }
@@ -329,11 +373,12 @@ pub struct DeezerApiAlbum {
     pub id: u32,
     pub title: String,
     #[serde(rename = "cover_medium")]
-    pub thumbnail: String,
+    pub thumbnail: Option<String>,
     #[serde(rename = "tracklist")]
     pub tracks: String,
 }
 
+// album track lists don't repeat the album on every track, hence it's optional
 #[derive(Deserialize, Debug)]
 pub struct DeezerApiTrack {
     pub id: u32,
@@ -343,64 +388,130 @@ pub struct DeezerApiTrack {
     pub duration: u16,
     pub isrc: Option<String>,
     pub artist: DeezerApiArtist,
-    pub album: DeezerApiAlbum,
+    pub album: Option<DeezerApiAlbum>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeezerApiFullAlbum {
+    pub id: u32,
+    pub title: String,
+    #[serde(rename = "cover_medium")]
+    pub thumbnail: Option<String>,
+    pub nb_tracks: Option<usize>,
+    pub tracks: DeezerData<Vec<DeezerApiTrack>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeezerApiPlaylist {
+    pub id: u64,
+    pub title: String,
+    #[serde(rename = "picture_medium")]
+    pub thumbnail: Option<String>,
+    pub nb_tracks: Option<usize>,
+    pub tracks: DeezerData<Vec<DeezerApiTrack>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeezerApiError {
+    #[serde(rename = "type")]
+    pub cause: String,
+    pub message: String,
+    pub code: u16,
 }
 
+// paged lists link to their next page
 #[derive(Deserialize, Debug)]
 pub struct DeezerData<T> {
     pub data: T,
+    pub next: Option<String>,
+}
+
+impl InternalDeezerMixTrack {
+    /**
+     * Maps private api tracks to the public api shape so they go through the same track mapping as search
+     */
+    pub fn to_api_track(&self) -> Option<DeezerApiTrack> {
+        let title = match self
+            .version
+            .as_deref()
+            .filter(|version| !version.is_empty())
+        {
+            Some(version) => format!("{} {}", self.sng_title, version),
+            None => self.sng_title.clone(),
+        };
+
+        Some(DeezerApiTrack {
+            id: self.sng_id.parse().ok()?,
+            readable: true,
+            title,
+            link: format!("https://www.deezer.com/track/{}", self.sng_id),
+            duration: self.duration.parse().unwrap_or(0),
+            isrc: self.isrc.clone(),
+            artist: DeezerApiArtist {
+                id: self.art_id.parse().unwrap_or(0),
+                name: self.art_name.clone(),
+                link: Some(format!("https://www.deezer.com/artist/{}", self.art_id)),
+                thumbnail: None,
+                tracks: format!("https://api.deezer.com/artist/{}/top", self.art_id),
+            },
+            album: Some(DeezerApiAlbum {
+                id: self.alb_id.parse().unwrap_or(0),
+                title: self.alb_title.clone(),
+                thumbnail: Some(format!(
+                    "https://e-cdns-images.dzcdn.net/images/cover/{}/250x250-000000-80-0-0.jpg",
+                    self.alb_picture
+                )),
+                tracks: format!("https://api.deezer.com/album/{}/tracks", self.alb_id),
+            }),
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Tokens {
+    pub arl: String,
     pub session_id: String,
     pub unique_id: String,
     pub check_form: String,
     pub license_token: String,
-    pub expire_at: Instant,
+    pub refresh_at: Instant,
 }
 
 impl Tokens {
     pub fn create_cookie(&self) -> String {
-        format!(
-            "arl={}; {}; {}",
-            Config
-                .deezer_config
-                .as_ref()
-                .expect("Unexpected Nullish Config")
-                .arl
-                .to_owned(),
-            self.session_id,
-            self.unique_id
-        )
+        format!("arl={}; {}; {}", self.arl, self.session_id, self.unique_id)
     }
 }
 
-impl DeezerQualityFormat {
-    pub fn new(data: &InternalDeezerSongData, quality: Option<DeezerQuality>) -> Self {
-        let mut quality = {
-            match quality.unwrap_or(DeezerQuality::Mp3_128) {
-                DeezerQuality::Flac => "FLAC",
-                DeezerQuality::Mp3_320 => "MP3_320",
-                DeezerQuality::Mp3_256 => "MP3_256",
-                DeezerQuality::Mp3_128 => "MP3_128",
-            }
-        };
-
-        if quality == "FLAC" && data.filesize_flac == "0" {
-            quality = "MP3_128";
+impl DeezerQuality {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeezerQuality::Flac => "FLAC",
+            DeezerQuality::Mp3_320 => "MP3_320",
+            DeezerQuality::Mp3_256 => "MP3_256",
+            DeezerQuality::Mp3_128 => "MP3_128",
         }
+    }
 
-        if quality == "MP3_320" && data.filesize_mp3_320 == "0" {
-            quality = "MP3_128";
-        }
+    /**
+     * Deezer reports a filesize of 0 for formats a track isn't available in
+     */
+    pub fn is_available(&self, data: &InternalDeezerSongData) -> bool {
+        let filesize = match self {
+            DeezerQuality::Flac => &data.filesize_flac,
+            DeezerQuality::Mp3_320 => &data.filesize_mp3_320,
+            DeezerQuality::Mp3_256 => &data.filesize_mp3_256,
+            DeezerQuality::Mp3_128 => &data.filesize_mp3_128,
+        };
 
-        if quality == "MP3_256" && data.filesize_mp3_256 == "0" {
-            quality = "MP3_128";
-        }
+        filesize != "0"
+    }
+}
 
+impl DeezerQualityFormat {
+    pub fn new(quality: DeezerQuality) -> Self {
         Self {
-            format: quality.to_string(),
+            format: quality.as_str().to_string(),
             cipher: String::from("BF_CBC_STRIPE"),
         }
     }
@@ -1,45 +1,48 @@
 use super::model::{
-    DeezerApiTrack, DeezerData, DeezerGetMedia, DeezerGetUrlBody, DeezerGetUrlMedia,
-    DeezerMakePlayableBody, DeezerQuality, DeezerQualityFormat, InternalDeezerGetUserData,
-    InternalDeezerResponse, InternalDeezerSongData,
+    DeezerApiArtist, DeezerApiError, DeezerApiFullAlbum, DeezerApiPlaylist, DeezerApiTrack,
+    DeezerArtistRadioBody, DeezerData, DeezerGetMedia, DeezerGetUrlBody, DeezerGetUrlMedia,
+    DeezerMakePlayableBody, DeezerQuality, DeezerQualityFormat, DeezerTrackMixBody,
+    InternalDeezerMixData, InternalDeezerSongData,
 };
 use super::stream::DeezerHttpStream;
+use super::token::TokenManager;
 use super::{MEDIA_BASE, PUBLIC_API_BASE};
 use super::{PRIVATE_API_BASE, model::Tokens};
-use crate::Config;
+use crate::util::config::DeezerConfig;
 use crate::util::encoder::encode_base64;
-use crate::util::source::Query;
-use crate::util::url::is_url;
+use crate::util::source::{Query, fail_source};
 use crate::{
-    models::{ApiTrack, ApiTrackInfo, ApiTrackResult},
+    models::{
+        ApiPlaylistInfo, ApiTrack, ApiTrackInfo, ApiTrackPlaylist, ApiTrackResult, PluginInfo,
+    },
     util::{errors::ResolverError, source::Source},
 };
+use async_trait::async_trait;
 use regex::Regex;
 use reqwest::{Body, Client};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
 use songbird::input::{Compose, HttpRequest, Input, LiveInput};
 use songbird::tracks::Track;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
-use tokio::time::Instant;
+
+const DEFAULT_PLAYLIST_TRACK_LIMIT: usize = 1000;
+// the most tracks deezer returns per page
+const TRACK_PAGE_SIZE: usize = 100;
 
 pub struct Deezer {
     client: Client,
-    tokens: Arc<Mutex<Option<Tokens>>>,
-    regex: Regex,
-    search_prefixes: (&'static str, &'static str, &'static str),
+    tokens: TokenManager,
+    url_patterns: Vec<Regex>,
+    search_prefixes: [&'static str; 3],
+    api_base: String,
+    playlist_track_limit: usize,
+    decrypt_key: String,
+    qualities: Vec<DeezerQuality>,
 }
 
+#[async_trait]
 impl Source for Deezer {
-    fn new(client: Option<Client>) -> Self {
-        Self {
-            client: client.unwrap_or_default(),
-            tokens: Arc::new(Mutex::new(None)),
-            regex: Regex::new("(https?://)?(www\\.)?deezer\\.com/(?<countrycode>[a-zA-Z]{2}/)?(?<type>track|album|playlist|artist)/(?<identifier>[0-9]+)").expect("Failed to init RegEx"),
-            search_prefixes: ("dzsearch:", "dzisrc:", "dzrec:"),
-        }
-    }
-
     fn get_name(&self) -> &'static str {
         "deezer"
     }
@@ -48,37 +51,28 @@ impl Source for Deezer {
         self.client.clone()
     }
 
-    fn parse_query(&self, query: &str) -> Option<Query> {
-        if !is_url(query) {
-            if query.starts_with(self.search_prefixes.0)
-                || query.starts_with(self.search_prefixes.1)
-                || query.starts_with(self.search_prefixes.2)
-            {
-                return Some(Query::Search(query.to_string()));
-            } else {
-                return None;
-            }
-        }
-
-        self.regex.captures(query)?;
+    fn search_prefixes(&self) -> &[&'static str] {
+        &self.search_prefixes
+    }
 
-        Some(Query::Url(query.to_string()))
+    fn url_patterns(&self) -> &[Regex] {
+        &self.url_patterns
     }
 
     async fn resolve(&self, query: Query) -> Result<ApiTrackResult, ResolverError> {
         match query {
-            Query::Url(_) => todo!(),
+            Query::Url(url) => self.resolve_url(url).await,
             Query::Search(input) => {
                 let mut data: Option<Vec<DeezerApiTrack>> = None;
 
-                if input.starts_with(self.search_prefixes.0) {
-                    let term = input.split_at(self.search_prefixes.0.len()).1;
+                if input.starts_with(self.search_prefixes[0]) {
+                    let term = input.split_at(self.search_prefixes[0].len()).1;
 
                     let query = [("q", term)];
 
                     let request = self
                         .client
-                        .get(format!("{PUBLIC_API_BASE}/search"))
+                        .get(format!("{}/search", self.api_base))
                         .query(&query)
                         .build()?;
 
@@ -91,116 +85,329 @@ impl Source for Deezer {
                     let tracks = response.json::<DeezerData<Vec<DeezerApiTrack>>>().await?;
 
                     let _ = data.insert(tracks.data);
-                } else if input.starts_with(self.search_prefixes.1) {
-                    let isrc = input.split_at(self.search_prefixes.1.len()).1;
-
-                    let request = self
-                        .client
-                        .get(format!("{PUBLIC_API_BASE}/track/isrc:{isrc}"))
-                        .build()?;
-
-                    let response = self.client.execute(request).await?;
+                } else if input.starts_with(self.search_prefixes[1]) {
+                    let isrc = input.split_at(self.search_prefixes[1].len()).1;
 
-                    if !response.status().is_success() {
+                    let Some(track) = self
+                        .get_public::<DeezerApiTrack>(&format!("track/isrc:{isrc}"))
+                        .await?
+                    else {
                         return Ok(ApiTrackResult::Empty(None));
-                    }
+                    };
+
+                    let _ = data.insert(vec![track]);
+                } else if input.starts_with(self.search_prefixes[2]) {
+                    let seed = input.split_at(self.search_prefixes[2].len()).1;
 
-                    let _ = data.insert(vec![response.json::<DeezerApiTrack>().await?]);
+                    return self.resolve_recommendations(seed).await;
                 }
 
                 let Some(api_tracks) = data else {
                     return Ok(ApiTrackResult::Empty(None));
                 };
 
-                let tracks = api_tracks
-                    .iter()
-                    .filter(|deezer_api_track| deezer_api_track.readable)
-                    .map(|deezer_api_track| {
-                        let info = ApiTrackInfo {
-                            identifier: deezer_api_track.id.to_string(),
-                            is_seekable: true,
-                            author: deezer_api_track.artist.name.clone(),
-                            length: (deezer_api_track.duration as u64) * 1000,
-                            is_stream: false,
-                            position: 0,
-                            title: deezer_api_track.title.clone(),
-                            uri: Some(deezer_api_track.link.clone()),
-                            artwork_url: Some(deezer_api_track.album.thumbnail.clone()),
-                            isrc: deezer_api_track.isrc.clone(),
-                            source_name: self.get_name().to_string(),
-                        };
-
-                        ApiTrack {
-                            encoded: encode_base64(&info).unwrap(),
-                            info,
-                            plugin_info: crate::models::Empty,
-                        }
-                    })
-                    .collect::<Vec<ApiTrack>>();
-
-                Ok(ApiTrackResult::Search(tracks))
+                Ok(ApiTrackResult::Search(self.make_tracks(&api_tracks, None)?))
             }
         }
     }
 
-    async fn make_playable(&self, track: ApiTrack) -> Result<Track, ResolverError> {
-        let tokens = self.get_token().await?;
+    async fn make_playable(&self, mut track: ApiTrack) -> Result<Track, ResolverError> {
+        let body = DeezerMakePlayableBody {
+            sng_id: track.info.identifier.clone(),
+        };
 
-        let response = {
-            let query = [
-                ("method", "song.getData"),
-                ("input", "3"),
-                ("api_version", "1.0"),
-                ("api_token", tokens.check_form.as_str()),
-            ];
+        let song = self
+            .call_private::<_, InternalDeezerSongData>("song.getData", &body)
+            .await?;
 
-            let body = DeezerMakePlayableBody {
-                sng_id: track.info.identifier.clone(),
-            };
+        let tokens = self.get_token().await?;
+
+        let mut chosen = None;
+        let mut last_error = None;
 
-            let request = self
-                .client
-                .post(PRIVATE_API_BASE)
-                .header("Cookie", tokens.create_cookie())
-                .body(Body::from(serde_json::to_string(&body)?))
-                .query(&query)
-                .build()?;
+        for quality in self
+            .qualities
+            .iter()
+            .copied()
+            .filter(|quality| quality.is_available(&song))
+        {
+            match self
+                .get_media_url(&tokens, &song.track_token, quality)
+                .await
+            {
+                Ok(url) => {
+                    chosen = Some((quality, url));
+                    break;
+                }
+                Err(error) => {
+                    tracing::debug!(
+                        "Deezer track [{}] is not available in [{}]: {}",
+                        track.info.identifier,
+                        quality.as_str(),
+                        error
+                    );
+                    last_error = Some(error);
+                }
+            }
+        }
 
-            self.client.execute(request).await?
+        let Some((quality, url)) = chosen else {
+            return Err(last_error.unwrap_or(ResolverError::MissingRequiredData(
+                "None of the configured deezer qualities are available",
+            )));
         };
 
+        track.plugin_info.insert(
+            String::from("format"),
+            Value::String(quality.as_str().to_string()),
+        );
+
+        let mut stream = DeezerHttpStream::new(
+            HttpRequest::new(self.get_client(), url),
+            self.get_track_key(track.info.identifier.clone()),
+        );
+
+        let input = Input::Live(LiveInput::Raw(stream.create_async().await?), None);
+
+        Ok(Track::new_with_data(input, Arc::new(track)))
+    }
+}
+
+impl Deezer {
+    /**
+     * Everything needed at play time is copied out of the config here, so a reload can't pull it away from tracks that are still loading
+     */
+    pub fn new(client: Option<Client>, config: &DeezerConfig) -> Self {
+        let client = client.unwrap_or_default();
+
+        Self {
+            tokens: TokenManager::new(client.clone(), config.arl.clone()),
+            client,
+            url_patterns: vec![
+                Regex::new("^https?://(www\\.)?deezer\\.com/(?<countrycode>[a-zA-Z]{2}/)?(?<type>track|album|playlist|artist)/(?<identifier>[0-9]+)").expect("Failed to init RegEx"),
+                Regex::new("^https?://(deezer\\.page\\.link|link\\.deezer\\.com)/\\S+").expect("Failed to init RegEx"),
+            ],
+            search_prefixes: ["dzsearch:", "dzisrc:", "dzrec:"],
+            api_base: PUBLIC_API_BASE.to_string(),
+            playlist_track_limit: config
+                .playlist_track_limit
+                .unwrap_or(DEFAULT_PLAYLIST_TRACK_LIMIT),
+            decrypt_key: config.decrypt_key.clone(),
+            qualities: config.qualities.clone().unwrap_or_else(|| {
+                vec![
+                    DeezerQuality::Flac,
+                    DeezerQuality::Mp3_320,
+                    DeezerQuality::Mp3_128,
+                ]
+            }),
+        }
+    }
+
+    /**
+     * Fetches from the public api, returns None when deezer answers with an error object
+     */
+    async fn get_public<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<Option<T>, ResolverError> {
+        self.get_url(&format!("{}/{path}", self.api_base)).await
+    }
+
+    /**
+     * Same as `get_public` but for full urls, like the `next` links of paged lists
+     */
+    async fn get_url<T: DeserializeOwned>(&self, url: &str) -> Result<Option<T>, ResolverError> {
+        let request = self.client.get(url).build()?;
+
+        let response = self.client.execute(request).await?;
+
         if !response.status().is_success() {
-            return Err(ResolverError::FailedStatusCode(
-                response.status().to_string(),
-            ));
+            return Ok(None);
+        }
+
+        let value = response.json::<Value>().await?;
+
+        if let Some(error) = value.get("error") {
+            let error = serde_json::from_value::<DeezerApiError>(error.clone())?;
+
+            tracing::debug!(
+                "Deezer public api returned an error for [{}]: {} ({})",
+                url,
+                error.message,
+                error.cause
+            );
+
+            return Ok(None);
         }
 
-        let response = {
-            let data = response
-                .json::<InternalDeezerResponse<InternalDeezerSongData>>()
-                .await?;
+        Ok(Some(serde_json::from_value::<T>(value)?))
+    }
+
+    async fn resolve_url(&self, url: String) -> Result<ApiTrackResult, ResolverError> {
+        // share links redirect to the full deezer.com url
+        let url = if self.url_patterns[1].is_match(&url) {
+            let response = self.client.get(url).send().await?;
 
-            let format = DeezerQualityFormat::new(&data.results, Some(DeezerQuality::Flac));
+            response.url().to_string()
+        } else {
+            url
+        };
 
-            let body = DeezerGetUrlBody {
-                license_token: tokens.license_token.clone(),
-                media: vec![DeezerGetUrlMedia {
-                    media_type: String::from("FULL"),
-                    formats: vec![format],
-                }],
-                track_tokens: vec![data.results.track_token],
+        let Some(captures) = self.url_patterns[0].captures(&url) else {
+            return Ok(ApiTrackResult::Empty(None));
+        };
+
+        let id = &captures["identifier"];
+
+        match &captures["type"] {
+            "track" => {
+                let Some(track) = self
+                    .get_public::<DeezerApiTrack>(&format!("track/{id}"))
+                    .await?
+                else {
+                    return Ok(ApiTrackResult::Empty(None));
+                };
+
+                if !track.readable {
+                    return Ok(ApiTrackResult::Empty(None));
+                }
+
+                Ok(ApiTrackResult::Track(self.make_track(&track, None)?))
+            }
+            "album" => {
+                let Some(album) = self
+                    .get_public::<DeezerApiFullAlbum>(&format!("album/{id}"))
+                    .await?
+                else {
+                    return Ok(ApiTrackResult::Empty(None));
+                };
+
+                let api_tracks = self
+                    .collect_tracks("album", id, album.tracks, album.nb_tracks)
+                    .await?;
+
+                let tracks = self.make_tracks(&api_tracks, album.thumbnail.as_deref())?;
+
+                Ok(self.make_playlist(album.title, tracks))
+            }
+            "playlist" => {
+                let Some(playlist) = self
+                    .get_public::<DeezerApiPlaylist>(&format!("playlist/{id}"))
+                    .await?
+                else {
+                    return Ok(ApiTrackResult::Empty(None));
+                };
+
+                let api_tracks = self
+                    .collect_tracks("playlist", id, playlist.tracks, playlist.nb_tracks)
+                    .await?;
+
+                let tracks = self.make_tracks(&api_tracks, None)?;
+
+                Ok(self.make_playlist(playlist.title, tracks))
+            }
+            "artist" => {
+                let Some(artist) = self
+                    .get_public::<DeezerApiArtist>(&format!("artist/{id}"))
+                    .await?
+                else {
+                    return Ok(ApiTrackResult::Empty(None));
+                };
+
+                let Some(top) = self
+                    .get_public::<DeezerData<Vec<DeezerApiTrack>>>(&format!(
+                        "artist/{id}/top?limit=50"
+                    ))
+                    .await?
+                else {
+                    return Ok(ApiTrackResult::Empty(None));
+                };
+
+                let tracks = self.make_tracks(&top.data, None)?;
+
+                Ok(self.make_playlist(format!("{}'s Top Tracks", artist.name), tracks))
+            }
+            _ => Ok(ApiTrackResult::Empty(None)),
+        }
+    }
+
+    /**
+     * Albums and playlists only embed their first page of tracks, the rest is paged from `/{kind}/{id}/tracks` up to the track limit
+     */
+    async fn collect_tracks(
+        &self,
+        kind: &str,
+        id: &str,
+        first_page: DeezerData<Vec<DeezerApiTrack>>,
+        total: Option<usize>,
+    ) -> Result<Vec<DeezerApiTrack>, ResolverError> {
+        let mut tracks = first_page.data;
+
+        let limit = total.unwrap_or(tracks.len()).min(self.playlist_track_limit);
+
+        let mut next = first_page.next.or_else(|| {
+            (tracks.len() < limit).then(|| {
+                format!(
+                    "{}/{kind}/{id}/tracks?index={}&limit={TRACK_PAGE_SIZE}",
+                    self.api_base,
+                    tracks.len()
+                )
+            })
+        });
+
+        while let Some(url) = next.take() {
+            if tracks.len() >= limit {
+                break;
+            }
+
+            let Some(page) = self
+                .get_url::<DeezerData<Vec<DeezerApiTrack>>>(&url)
+                .await?
+            else {
+                break;
             };
 
-            let request = self
-                .client
-                .post(format!("{MEDIA_BASE}/get_url"))
-                .header("Cookie", tokens.create_cookie())
-                .body(Body::from(serde_json::to_string(&body)?))
-                .build()?;
+            if page.data.is_empty() {
+                break;
+            }
+
+            tracks.extend(page.data);
+
+            next = page.next;
+        }
+
+        tracks.truncate(self.playlist_track_limit);
+
+        Ok(tracks)
+    }
 
-            self.client.execute(request).await?
+    /**
+     * Asks for a stream url in a single quality, errors if deezer can't provide it
+     */
+    async fn get_media_url(
+        &self,
+        tokens: &Tokens,
+        track_token: &str,
+        quality: DeezerQuality,
+    ) -> Result<String, ResolverError> {
+        let body = DeezerGetUrlBody {
+            license_token: tokens.license_token.clone(),
+            media: vec![DeezerGetUrlMedia {
+                media_type: String::from("FULL"),
+                formats: vec![DeezerQualityFormat::new(quality)],
+            }],
+            track_tokens: vec![track_token.to_string()],
         };
 
+        let request = self
+            .client
+            .post(format!("{MEDIA_BASE}/get_url"))
+            .header("Cookie", tokens.create_cookie())
+            .body(Body::from(serde_json::to_string(&body)?))
+            .build()?;
+
+        let response = self.client.execute(request).await?;
+
         if !response.status().is_success() {
             return Err(ResolverError::FailedStatusCode(
                 response.status().to_string(),
@@ -209,6 +416,17 @@ impl Source for Deezer {
 
         let json = response.json::<DeezerGetMedia>().await?;
 
+        if let Some(error) = json.error.as_ref().and_then(|errors| errors.first()) {
+            if error.message.to_lowercase().contains("license") {
+                self.tokens.invalidate().await;
+            }
+
+            return Err(ResolverError::FailedStatusCode(format!(
+                "{} {}",
+                error.code, error.message
+            )));
+        }
+
         let data = json
             .data
             .ok_or(ResolverError::MissingRequiredData("media.data"))?;
@@ -227,121 +445,455 @@ impl Source for Deezer {
                 "media.data.first().media.first().sources.first()",
             ))?;
 
-        let mut stream = DeezerHttpStream::new(
-            HttpRequest::new(self.get_client(), media.url.clone()),
-            self.get_track_key(track.info.identifier.clone()),
-        );
+        Ok(media.url.clone())
+    }
 
-        let input = Input::Live(LiveInput::Raw(stream.create_async().await?), None);
+    /**
+     * Posts to the private api with the current session, failing if deezer reports an error
+     */
+    async fn call_private<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: &str,
+        body: &B,
+    ) -> Result<T, ResolverError> {
+        let tokens = self.get_token().await?;
 
-        Ok(Track::new_with_data(input, Arc::new(track)))
+        let query = [
+            ("method", method),
+            ("input", "3"),
+            ("api_version", "1.0"),
+            ("api_token", tokens.check_form.as_str()),
+        ];
+
+        let request = self
+            .client
+            .post(PRIVATE_API_BASE)
+            .header("Cookie", tokens.create_cookie())
+            .body(Body::from(serde_json::to_string(body)?))
+            .query(&query)
+            .build()?;
+
+        let response = self.client.execute(request).await?;
+
+        if !response.status().is_success() {
+            return Err(ResolverError::FailedStatusCode(
+                response.status().to_string(),
+            ));
+        }
+
+        let mut value = response.json::<Value>().await?;
+
+        // the error field is an empty array on success and an object on failure
+        if let Some(error) = value
+            .get("error")
+            .filter(|error| error.as_object().is_some_and(|error| !error.is_empty()))
+        {
+            if error.get("VALID_TOKEN_REQUIRED").is_some() {
+                self.tokens.invalidate().await;
+            }
+
+            return Err(ResolverError::FailedStatusCode(error.to_string()));
+        }
+
+        Ok(serde_json::from_value::<T>(value["results"].take())?)
     }
-}
 
-impl Deezer {
+    /**
+     * Takes a track id, `track=<id>` for a track mix or `artist=<id>` for an artist radio
+     */
+    async fn resolve_recommendations(&self, seed: &str) -> Result<ApiTrackResult, ResolverError> {
+        let (kind, id) = seed.split_once('=').unwrap_or(("track", seed));
+
+        if id.is_empty() || !id.chars().all(|char| char.is_ascii_digit()) {
+            return Ok(ApiTrackResult::Empty(None));
+        }
+
+        let mix = match kind {
+            "track" => {
+                let body = DeezerTrackMixBody {
+                    sng_id: id.to_string(),
+                    start_with_input_track: true,
+                };
+
+                self.call_private::<_, InternalDeezerMixData>("song.getSearchTrackMix", &body)
+                    .await?
+            }
+            "artist" => {
+                let body = DeezerArtistRadioBody {
+                    art_id: id.to_string(),
+                };
+
+                self.call_private::<_, InternalDeezerMixData>("smart.getSmartRadio", &body)
+                    .await?
+            }
+            _ => return Ok(ApiTrackResult::Empty(None)),
+        };
+
+        let api_tracks = mix
+            .data
+            .iter()
+            .filter_map(|track| track.to_api_track())
+            .collect::<Vec<DeezerApiTrack>>();
+
+        let name = match (kind, api_tracks.first()) {
+            ("track", Some(track)) => format!("Deezer Mix: {}", track.title),
+            ("artist", Some(track)) => format!("Deezer Radio: {}", track.artist.name),
+            _ => String::from("Deezer Mix"),
+        };
+
+        let tracks = self.make_tracks(&api_tracks, None)?;
+
+        Ok(self.make_playlist(name, tracks))
+    }
+
+    /**
+     * Album track lists don't carry their album, so the album cover can be passed as the artwork
+     */
+    fn make_track(
+        &self,
+        track: &DeezerApiTrack,
+        artwork_url: Option<&str>,
+    ) -> Result<ApiTrack, ResolverError> {
+        let info = ApiTrackInfo {
+            identifier: track.id.to_string(),
+            is_seekable: true,
+            author: track.artist.name.clone(),
+            length: (track.duration as u64) * 1000,
+            is_stream: false,
+            position: 0,
+            title: track.title.clone(),
+            uri: Some(track.link.clone()),
+            artwork_url: track
+                .album
+                .as_ref()
+                .and_then(|album| album.thumbnail.clone())
+                .or(artwork_url.map(String::from)),
+            isrc: track.isrc.clone(),
+            source_name: self.get_name().to_string(),
+        };
+
+        Ok(ApiTrack {
+            encoded: encode_base64(&info)?,
+            info,
+            plugin_info: PluginInfo::new(),
+        })
+    }
+
+    fn make_tracks(
+        &self,
+        tracks: &[DeezerApiTrack],
+        artwork_url: Option<&str>,
+    ) -> Result<Vec<ApiTrack>, ResolverError> {
+        tracks
+            .iter()
+            .filter(|track| track.readable)
+            .map(|track| self.make_track(track, artwork_url))
+            .collect()
+    }
+
+    fn make_playlist(&self, name: String, tracks: Vec<ApiTrack>) -> ApiTrackResult {
+        if tracks.is_empty() {
+            return ApiTrackResult::Empty(None);
+        }
+
+        ApiTrackResult::Playlist(ApiTrackPlaylist {
+            info: ApiPlaylistInfo {
+                name,
+                selected_track: -1,
+            },
+            plugin_info: PluginInfo::new(),
+            tracks,
+        })
+    }
+
+    /**
+     * Warms up the session, failures are logged since the session is fetched again on demand
+     */
     pub async fn init(&self) {
-        self.get_token().await.unwrap();
+        if let Err(error) = self.get_token().await {
+            tracing::error!("Failed to create a deezer session: {}", error);
+        }
     }
 
     fn get_track_key(&self, id: String) -> [u8; 16] {
         let md5 = hex::encode(md5::compute(id).0);
         let hash = md5.as_bytes();
+        let decrypt_key = self.decrypt_key.as_bytes();
 
         let mut key: [u8; 16] = [0; 16];
 
         for i in 0..16 {
-            key[i] = hash[i]
-                ^ hash[i + 16]
-                ^ Config
-                    .deezer_config
-                    .as_ref()
-                    .expect("Unexpected Nullish Config")
-                    .decrypt_key
-                    .as_bytes()[i];
+            key[i] = hash[i] ^ hash[i + 16] ^ decrypt_key[i];
         }
 
         key
     }
 
+    /**
+     * Bad credentials disable the source until it is registered again with a working arl
+     */
     async fn get_token(&self) -> Result<Tokens, ResolverError> {
-        let mut guard = self.tokens.lock().await;
+        let result = self.tokens.get().await;
 
-        if let Some(token) = guard.as_ref() {
-            if Instant::now().duration_since(token.expire_at).as_secs() > 3600 {
-                return Ok(token.clone());
-            }
+        if let Err(error @ ResolverError::InvalidCredentials(_)) = &result {
+            fail_source(self.get_name(), error);
         }
 
-        let query = [
-            ("method", "deezer.getUserData"),
-            ("input", "3"),
-            ("api_version", "1.0"),
-            ("api_token", ""),
-        ];
+        result
+    }
+}
 
-        let request = self
-            .client
-            .post(PRIVATE_API_BASE)
-            .header("Content-Length", "0")
-            .header(
-                "Cookie",
-                format!(
-                    "arl={}",
-                    Config
-                        .deezer_config
-                        .as_ref()
-                        .expect("Unexpected Nullish Config")
-                        .arl
-                        .to_owned()
-                ),
-            )
-            .query(&query)
-            .build()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::decoder::decode_base64;
+    use axum::{
+        Router,
+        extract::{Query as QueryParams, State},
+        http::{Uri, header::CONTENT_TYPE},
+        response::{IntoResponse, Redirect, Response},
+    };
+    use std::collections::HashMap;
+    use tokio::net::TcpListener;
+
+    async fn fixture(
+        State(base): State<String>,
+        QueryParams(params): QueryParams<HashMap<String, String>>,
+        uri: Uri,
+    ) -> Response {
+        let body = match (uri.path(), params.get("index").map(String::as_str)) {
+            ("/track/3135556", _) => include_str!("../../../tests/fixtures/deezer/track.json"),
+            ("/album/302127", _) => include_str!("../../../tests/fixtures/deezer/album.json"),
+            ("/album/302127/tracks", Some("2")) => {
+                include_str!("../../../tests/fixtures/deezer/album_tracks_2.json")
+            }
+            ("/playlist/908622995", _) => {
+                include_str!("../../../tests/fixtures/deezer/playlist.json")
+            }
+            ("/playlist/908622995/tracks", Some("2")) => {
+                include_str!("../../../tests/fixtures/deezer/playlist_tracks_2.json")
+            }
+            ("/playlist/908622995/tracks", Some("4")) => {
+                include_str!("../../../tests/fixtures/deezer/playlist_tracks_4.json")
+            }
+            ("/artist/27", _) => include_str!("../../../tests/fixtures/deezer/artist.json"),
+            ("/artist/27/top", _) => {
+                include_str!("../../../tests/fixtures/deezer/artist_top.json")
+            }
+            // share links redirect to the web url, which is served by this server as well
+            ("/share/discovery", _) => {
+                return Redirect::to("http://www.deezer.com/en/album/302127").into_response();
+            }
+            ("/en/album/302127", _) => return "Discovery".into_response(),
+            _ => include_str!("../../../tests/fixtures/deezer/error.json"),
+        };
 
-        let response = self.client.execute(request).await?;
+        (
+            [(CONTENT_TYPE, "application/json")],
+            body.replace("{base}", &base),
+        )
+            .into_response()
+    }
 
-        if !response.status().is_success() {
-            return Err(ResolverError::FailedStatusCode(
-                response.status().to_string(),
-            ));
+    fn config(playlist_track_limit: Option<usize>) -> DeezerConfig {
+        DeezerConfig {
+            decrypt_key: String::from("0123456789abcdef"),
+            arl: String::from("arl"),
+            qualities: None,
+            playlist_track_limit,
         }
+    }
 
-        let headers = response
-            .headers()
-            .get_all("Set-Cookie")
-            .iter()
-            .map(|header| header.to_str())
-            .filter(|header| header.is_ok())
-            .map(|header| header.unwrap().to_string())
-            .collect::<Vec<String>>();
+    async fn deezer(playlist_track_limit: Option<usize>) -> Deezer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let base = format!("http://{addr}");
+
+        tokio::spawn(async move {
+            let router = Router::new().fallback(fixture).with_state(base);
+
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        let client = Client::builder()
+            .no_proxy()
+            .resolve("deezer.page.link", addr)
+            .resolve("www.deezer.com", addr)
+            .build()
+            .unwrap();
+
+        let mut deezer = Deezer::new(Some(client), &config(playlist_track_limit));
 
-        let session_id = headers.iter().find(|str| str.starts_with("sid=")).ok_or(
-            ResolverError::MissingRequiredData("Missing Deezer Session Id"),
-        )?;
+        deezer.api_base = format!("http://{addr}");
 
-        let unique_id = headers
+        deezer
+    }
+
+    async fn resolve(deezer: &Deezer, url: &str) -> ApiTrackResult {
+        deezer.resolve(Query::Url(url.to_string())).await.unwrap()
+    }
+
+    fn playlist(result: ApiTrackResult) -> ApiTrackPlaylist {
+        let ApiTrackResult::Playlist(playlist) = result else {
+            panic!("Expected a playlist, got {result:?}");
+        };
+
+        playlist
+    }
+
+    fn titles(playlist: &ApiTrackPlaylist) -> Vec<&str> {
+        playlist
+            .tracks
             .iter()
-            .find(|str| str.starts_with("dzr_uniq_id="))
-            .ok_or(ResolverError::MissingRequiredData(
-                "Missing Deezer Unique Id",
-            ))?;
+            .map(|track| track.info.title.as_str())
+            .collect()
+    }
 
-        let data = response
-            .json::<InternalDeezerResponse<InternalDeezerGetUserData>>()
-            .await?;
+    #[tokio::test]
+    async fn makes_a_track() {
+        let deezer = deezer(None).await;
 
-        let tokens = Tokens {
-            session_id: (*session_id).to_string(),
-            unique_id: (*unique_id).to_string(),
-            check_form: data.results.check_form,
-            license_token: data.results.user.options.license_token,
-            expire_at: Instant::now()
-                .checked_add(Duration::from_secs(3600))
-                .ok_or(ResolverError::MissingRequiredData("Invalid Expire At"))?,
+        let result = resolve(&deezer, "https://www.deezer.com/en/track/3135556").await;
+
+        let ApiTrackResult::Track(track) = result else {
+            panic!("Expected a track, got {result:?}");
         };
 
-        let _ = guard.insert(tokens.clone());
+        assert_eq!(track.info.identifier, "3135556");
+        assert_eq!(track.info.title, "Harder, Better, Faster, Stronger");
+        assert_eq!(track.info.author, "Daft Punk");
+        assert_eq!(track.info.length, 224_000);
+        assert_eq!(track.info.isrc.as_deref(), Some("GBDUW0000059"));
+        assert_eq!(
+            track.info.uri.as_deref(),
+            Some("https://www.deezer.com/track/3135556")
+        );
+        assert!(
+            track
+                .info
+                .artwork_url
+                .as_deref()
+                .is_some_and(|url| url.contains("2e018122cb56986277102d2041a592c8"))
+        );
+        assert_eq!(track.info.source_name, "deezer");
+        assert_eq!(decode_base64(&track.encoded).unwrap(), track.info);
+    }
 
-        Ok(tokens)
+    #[tokio::test]
+    async fn pages_album_tracks_with_the_album_cover() {
+        let deezer = deezer(None).await;
+
+        let playlist = playlist(resolve(&deezer, "https://www.deezer.com/album/302127").await);
+
+        assert_eq!(playlist.info.name, "Discovery");
+        assert_eq!(playlist.info.selected_track, -1);
+        assert_eq!(
+            titles(&playlist),
+            ["One More Time", "Aerodynamic", "Digital Love"]
+        );
+        assert!(playlist.tracks.iter().all(|track| {
+            track
+                .info
+                .artwork_url
+                .as_deref()
+                .is_some_and(|url| url.contains("/images/cover/"))
+        }));
+    }
+
+    #[tokio::test]
+    async fn follows_playlist_pages_and_skips_unreadable_tracks() {
+        let deezer = deezer(None).await;
+
+        let playlist =
+            playlist(resolve(&deezer, "https://www.deezer.com/playlist/908622995").await);
+
+        assert_eq!(playlist.info.name, "Daft Punk Essentials");
+        assert_eq!(
+            titles(&playlist),
+            [
+                "One More Time",
+                "Harder, Better, Faster, Stronger",
+                "Aerodynamic",
+                "Digital Love"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_paging_at_the_track_limit() {
+        let deezer = deezer(Some(3)).await;
+
+        let playlist =
+            playlist(resolve(&deezer, "https://www.deezer.com/playlist/908622995").await);
+
+        assert_eq!(
+            titles(&playlist),
+            [
+                "One More Time",
+                "Harder, Better, Faster, Stronger",
+                "Aerodynamic"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn loads_artist_top_tracks() {
+        let deezer = deezer(None).await;
+
+        let playlist = playlist(resolve(&deezer, "https://www.deezer.com/artist/27").await);
+
+        assert_eq!(playlist.info.name, "Daft Punk's Top Tracks");
+        assert_eq!(playlist.tracks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn follows_share_link_redirects() {
+        let deezer = deezer(None).await;
+
+        let playlist = playlist(resolve(&deezer, "http://deezer.page.link/share/discovery").await);
+
+        assert_eq!(playlist.info.name, "Discovery");
+        assert_eq!(playlist.tracks.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn api_errors_are_empty() {
+        let deezer = deezer(None).await;
+
+        assert_eq!(
+            resolve(&deezer, "https://www.deezer.com/track/1").await,
+            ApiTrackResult::Empty(None)
+        );
+    }
+
+    #[test]
+    fn empty_playlists_are_empty() {
+        let deezer = Deezer::new(None, &config(None));
+
+        assert_eq!(
+            deezer.make_playlist(String::from("Nothing"), Vec::new()),
+            ApiTrackResult::Empty(None)
+        );
+    }
+
+    // the global config isn't loaded in tests, so this only passes if playback reads the copied values
+    #[test]
+    fn playback_settings_are_copied_from_the_config() {
+        let deezer = Deezer::new(
+            None,
+            &DeezerConfig {
+                qualities: Some(vec![DeezerQuality::Mp3_128]),
+                ..config(None)
+            },
+        );
+
+        assert_eq!(deezer.qualities, [DeezerQuality::Mp3_128]);
+        assert_eq!(
+            deezer.get_track_key(String::from("3135556")),
+            [
+                59, 105, 49, 52, 56, 107, 109, 99, 109, 102, 98, 100, 102, 110, 48, 110
+            ]
+        );
+        assert_eq!(Deezer::new(None, &config(None)).qualities.len(), 3);
     }
 }
@@ -4,13 +4,17 @@ use async_trait::async_trait;
 use blowfish::Blowfish;
 use cbc::cipher::{KeyIvInit, block_padding::NoPadding};
 use cbc::{Decryptor, cipher::BlockDecryptMut};
+use reqwest::header::{HeaderValue, RANGE};
 use songbird::input::{AudioStream, AudioStreamError, Compose, HttpRequest};
 use std::cmp::min;
 use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom};
 use symphonia::core::io::MediaSource;
+use tokio::runtime::Handle;
 use tokio::task::block_in_place;
 
 static CHUNK_SIZE: usize = 2048;
+// forward seeks shorter than this read through instead of making a new request
+static MAX_SKIP_BYTES: usize = CHUNK_SIZE * 32;
 
 pub struct DeezerHttpStream {
     request: HttpRequest,
@@ -30,8 +34,12 @@ impl Compose for DeezerHttpStream {
         let hint = request.hint;
 
         Ok(AudioStream {
-            input: Box::new(DeezerMediaSource::new(request.input, self.key))
-                as Box<dyn MediaSource>,
+            input: Box::new(DeezerMediaSource::new(
+                request.input,
+                self.request.clone(),
+                Handle::current(),
+                self.key,
+            )) as Box<dyn MediaSource>,
             hint,
         })
     }
@@ -43,76 +51,58 @@ impl Compose for DeezerHttpStream {
 
 /**
  * Deezer don't support the global seeker due to custom deserializing, hence it needs to be implemented manually
- * PS I could probably have the universal seek support this but probs i'll do it at some point
+ * Bytes read from the start are kept so seeking back is free, seeking past them opens a range request on a chunk boundary
  */
 pub struct DeezerMediaSource {
     source: Box<dyn MediaSource>,
+    request: HttpRequest,
+    handle: Handle,
     key: [u8; 16],
     buffer: [u8; CHUNK_SIZE],
     position: usize,
+    source_position: usize,
+    chunk: Vec<u8>,
+    chunk_start: usize,
     downloaded: Vec<u8>,
-    downloaded_bytes: usize,
     total_bytes: Option<usize>,
 }
 
 impl Read for DeezerMediaSource {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        if self.position < self.downloaded_bytes {
-            let mut read_up_to = self.position + buf.len();
-
-            if read_up_to > self.downloaded_bytes {
-                read_up_to = self.downloaded_bytes;
-            }
-
-            let bytes = &self.downloaded[self.position..read_up_to];
-            let bytes_read = bytes.len();
-
-            buf[0..bytes_read].copy_from_slice(bytes);
+        if self.position < self.downloaded.len() {
+            let bytes_read = copy_from(buf, &self.downloaded, 0, self.position);
 
             self.position += bytes_read;
 
             return Ok(bytes_read);
         }
 
-        let mut total_read = 0;
-
-        while total_read < CHUNK_SIZE {
-            let bytes_read = block_in_place(|| self.source.read(&mut self.buffer[total_read..]))?;
-
-            if bytes_read == 0 {
-                break;
-            }
-
-            total_read += bytes_read;
+        if self
+            .total_bytes
+            .is_some_and(|total_bytes| self.position >= total_bytes)
+        {
+            return Ok(0);
         }
 
-        let current_chunk = (self.downloaded_bytes as f64 / CHUNK_SIZE as f64).ceil() as usize;
+        if !self.chunk_contains(self.position) {
+            let target = self.position - self.position % CHUNK_SIZE;
 
-        if current_chunk % 3 > 0 || total_read < CHUNK_SIZE {
-            self.downloaded.extend(self.buffer[..total_read].iter());
-        } else {
-            let decryptor: Decryptor<Blowfish> = Decryptor::new_from_slices(&self.key, &SECRET_IV)
-                .map_err(|error| IoError::new(ErrorKind::Unsupported, error))?;
-
-            let decrypted = decryptor
-                .decrypt_padded_mut::<NoPadding>(&mut self.buffer[..total_read])
-                .map_err(|error| IoError::new(ErrorKind::InvalidInput, error.to_string()))?;
-
-            self.downloaded.extend(decrypted[..total_read].iter());
-        }
-
-        self.downloaded_bytes += total_read;
+            if target < self.source_position || target - self.source_position > MAX_SKIP_BYTES {
+                self.reopen(target)?;
+            }
 
-        let mut read_up_to = self.position + min(buf.len(), self.downloaded_bytes);
+            while self.source_position <= target {
+                if self.next_chunk()? == 0 {
+                    return Ok(0);
+                }
+            }
 
-        if read_up_to > self.downloaded_bytes {
-            read_up_to = self.downloaded_bytes;
+            if !self.chunk_contains(self.position) {
+                return Ok(0);
+            }
         }
 
-        let bytes = &self.downloaded[self.position..read_up_to];
-        let bytes_read = bytes.len();
-
-        buf[0..bytes_read].copy_from_slice(bytes);
+        let bytes_read = copy_from(buf, &self.chunk, self.chunk_start, self.position);
 
         self.position += bytes_read;
 
@@ -160,24 +150,103 @@ impl MediaSource for DeezerMediaSource {
     }
 
     fn byte_len(&self) -> Option<u64> {
-        self.source.byte_len()
+        self.total_bytes.map(|total_bytes| total_bytes as u64)
     }
 }
 
 impl DeezerMediaSource {
-    pub fn new(source: Box<dyn MediaSource>, key: [u8; 16]) -> Self {
+    pub fn new(
+        source: Box<dyn MediaSource>,
+        request: HttpRequest,
+        handle: Handle,
+        key: [u8; 16],
+    ) -> Self {
         let total_bytes = block_in_place(|| source.byte_len().map(|size| size as usize));
 
         Self {
             source,
+            request,
+            handle,
             key,
             buffer: [0; CHUNK_SIZE],
             downloaded: create_vec_with_capacity(total_bytes),
             position: 0,
-            downloaded_bytes: 0,
+            source_position: 0,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            chunk_start: 0,
             total_bytes,
         }
     }
+
+    fn chunk_contains(&self, position: usize) -> bool {
+        position >= self.chunk_start && position < self.chunk_start + self.chunk.len()
+    }
+
+    /**
+     * Reads the next chunk from the source, only every third full chunk is encrypted
+     */
+    fn next_chunk(&mut self) -> IoResult<usize> {
+        let mut total_read = 0;
+
+        while total_read < CHUNK_SIZE {
+            let bytes_read = block_in_place(|| self.source.read(&mut self.buffer[total_read..]))?;
+
+            if bytes_read == 0 {
+                break;
+            }
+
+            total_read += bytes_read;
+        }
+
+        if (self.source_position / CHUNK_SIZE) % 3 == 0 && total_read == CHUNK_SIZE {
+            let decryptor: Decryptor<Blowfish> = Decryptor::new_from_slices(&self.key, &SECRET_IV)
+                .map_err(|error| IoError::new(ErrorKind::Unsupported, error))?;
+
+            decryptor
+                .decrypt_padded_mut::<NoPadding>(&mut self.buffer[..total_read])
+                .map_err(|error| IoError::new(ErrorKind::InvalidInput, error.to_string()))?;
+        }
+
+        // keep growing the downloaded bytes as long as we are reading from where they end
+        if self.source_position == self.downloaded.len() {
+            self.downloaded.extend(self.buffer[..total_read].iter());
+        }
+
+        self.chunk.clear();
+        self.chunk.extend(self.buffer[..total_read].iter());
+        self.chunk_start = self.source_position;
+        self.source_position += total_read;
+
+        Ok(total_read)
+    }
+
+    /**
+     * Replaces the source with a range request starting at the given chunk boundary
+     */
+    fn reopen(&mut self, offset: usize) -> IoResult<()> {
+        let mut request = self.request.clone();
+
+        let range = HeaderValue::from_str(&format!("bytes={offset}-")).map_err(IoError::other)?;
+
+        request.headers.insert(RANGE, range);
+
+        let stream = block_in_place(|| self.handle.block_on(request.create_async()))
+            .map_err(IoError::other)?;
+
+        self.source = stream.input;
+        self.source_position = offset;
+
+        Ok(())
+    }
+}
+
+fn copy_from(buf: &mut [u8], bytes: &[u8], start: usize, position: usize) -> usize {
+    let bytes = &bytes[position - start..];
+    let bytes_read = min(buf.len(), bytes.len());
+
+    buf[0..bytes_read].copy_from_slice(&bytes[..bytes_read]);
+
+    bytes_read
 }
 
 impl DeezerHttpStream {
@@ -185,3 +254,203 @@ impl DeezerHttpStream {
         Self { request, key }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::Body,
+        extract::State,
+        http::{
+            HeaderMap, StatusCode,
+            header::{ACCEPT_RANGES, CONTENT_RANGE},
+        },
+        response::{IntoResponse, Response},
+        routing,
+    };
+    use cbc::{Encryptor, cipher::BlockEncryptMut};
+    use reqwest::Client;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    const KEY: [u8; 16] = *b"0123456789abcdef";
+    // 39 full chunks and a short one, whose index is a multiple of 3
+    const FULL_CHUNKS: usize = 39;
+    const LAST_CHUNK: usize = 1000;
+
+    struct Fixture {
+        plain: Vec<u8>,
+        encrypted: Vec<u8>,
+        ranges: Mutex<Vec<Option<String>>>,
+    }
+
+    fn fixture() -> Fixture {
+        let plain = (0..FULL_CHUNKS * CHUNK_SIZE + LAST_CHUNK)
+            .map(|index| (index * 31 + index / CHUNK_SIZE) as u8)
+            .collect::<Vec<u8>>();
+
+        let mut encrypted = plain.clone();
+
+        for (index, chunk) in encrypted.chunks_mut(CHUNK_SIZE).enumerate() {
+            if index % 3 != 0 || chunk.len() < CHUNK_SIZE {
+                continue;
+            }
+
+            Encryptor::<Blowfish>::new_from_slices(&KEY, &SECRET_IV)
+                .unwrap()
+                .encrypt_padded_mut::<NoPadding>(chunk, CHUNK_SIZE)
+                .unwrap();
+        }
+
+        Fixture {
+            plain,
+            encrypted,
+            ranges: Mutex::new(Vec::new()),
+        }
+    }
+
+    async fn serve(State(fixture): State<Arc<Fixture>>, headers: HeaderMap) -> Response {
+        let range = headers
+            .get(RANGE)
+            .and_then(|range| range.to_str().ok())
+            .map(String::from);
+
+        fixture.ranges.lock().unwrap().push(range.clone());
+
+        let length = fixture.encrypted.len();
+
+        let Some(start) = range
+            .as_deref()
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.split('-').next())
+            .and_then(|start| start.parse::<usize>().ok())
+        else {
+            return (
+                [(ACCEPT_RANGES, String::from("bytes"))],
+                fixture.encrypted.clone(),
+            )
+                .into_response();
+        };
+
+        (
+            StatusCode::PARTIAL_CONTENT,
+            [
+                (ACCEPT_RANGES, String::from("bytes")),
+                (
+                    CONTENT_RANGE,
+                    format!("bytes {start}-{}/{length}", length - 1),
+                ),
+            ],
+            Body::from(fixture.encrypted[start..].to_vec()),
+        )
+            .into_response()
+    }
+
+    async fn open() -> (Arc<Fixture>, Box<dyn MediaSource>) {
+        let fixture = Arc::new(fixture());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/track", listener.local_addr().unwrap());
+
+        let router = Router::new()
+            .route("/track", routing::get(serve))
+            .with_state(fixture.clone());
+
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let client = Client::builder().no_proxy().build().unwrap();
+
+        let stream = DeezerHttpStream::new(HttpRequest::new(client, url), KEY)
+            .create_async()
+            .await
+            .unwrap();
+
+        (fixture, stream.input)
+    }
+
+    fn read_at(source: &mut Box<dyn MediaSource>, position: u64, length: usize) -> Vec<u8> {
+        source.seek(SeekFrom::Start(position)).unwrap();
+
+        let mut buf = vec![0; length];
+
+        source.read_exact(&mut buf).unwrap();
+
+        buf
+    }
+
+    fn ranges(fixture: &Fixture) -> Vec<Option<String>> {
+        fixture.ranges.lock().unwrap().clone()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn decrypts_every_third_chunk_in_order() {
+        let (fixture, mut source) = open().await;
+
+        assert_eq!(source.byte_len(), Some(fixture.plain.len() as u64));
+
+        let mut output = Vec::new();
+
+        source.read_to_end(&mut output).unwrap();
+
+        assert!(output == fixture.plain);
+        assert_eq!(ranges(&fixture), [None]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn seeks_back_into_downloaded_bytes() {
+        let (fixture, mut source) = open().await;
+
+        read_at(&mut source, 0, CHUNK_SIZE * 4 + 100);
+
+        assert!(read_at(&mut source, 500, 3000) == fixture.plain[500..3500]);
+        assert_eq!(ranges(&fixture), [None]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reads_through_short_forward_seeks() {
+        let (fixture, mut source) = open().await;
+
+        read_at(&mut source, 0, 100);
+
+        let position = CHUNK_SIZE * 10 + 7;
+
+        assert!(
+            read_at(&mut source, position as u64, CHUNK_SIZE * 2)
+                == fixture.plain[position..position + CHUNK_SIZE * 2]
+        );
+        assert_eq!(ranges(&fixture), [None]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn long_forward_seeks_request_a_chunk_boundary() {
+        let (fixture, mut source) = open().await;
+
+        read_at(&mut source, 0, 100);
+
+        let position = CHUNK_SIZE * 36 + 300;
+
+        assert!(position - CHUNK_SIZE > MAX_SKIP_BYTES);
+        assert!(
+            read_at(&mut source, position as u64, CHUNK_SIZE)
+                == fixture.plain[position..position + CHUNK_SIZE]
+        );
+        assert_eq!(
+            ranges(&fixture),
+            [None, Some(format!("bytes={}-", CHUNK_SIZE * 36))]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn leaves_a_short_final_chunk_as_is() {
+        let (fixture, mut source) = open().await;
+
+        source.seek(SeekFrom::End(-(LAST_CHUNK as i64))).unwrap();
+
+        let mut output = Vec::new();
+
+        source.read_to_end(&mut output).unwrap();
+
+        assert!(output == fixture.plain[FULL_CHUNKS * CHUNK_SIZE..]);
+    }
+}
@@ -0,0 +1,206 @@
+use super::PRIVATE_API_BASE;
+use super::model::{InternalDeezerGetUserData, Tokens};
+use crate::util::errors::ResolverError;
+use reqwest::Client;
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tokio::time::{Instant, sleep};
+
+// deezer sessions are refreshed a bit before they actually expire
+const SESSION_LIFETIME: Duration = Duration::from_secs(3600);
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+const MAX_ATTEMPTS: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+
+/**
+ * Holds the deezer session. The lock is held while refreshing, so concurrent callers wait for one refresh instead of starting their own
+ */
+pub struct TokenManager {
+    client: Client,
+    arl: String,
+    tokens: Mutex<Option<Tokens>>,
+}
+
+impl TokenManager {
+    pub fn new(client: Client, arl: String) -> Self {
+        Self {
+            client,
+            arl,
+            tokens: Mutex::new(None),
+        }
+    }
+
+    pub async fn get(&self) -> Result<Tokens, ResolverError> {
+        let mut guard = self.tokens.lock().await;
+
+        if let Some(tokens) = guard.as_ref() {
+            if Instant::now() < tokens.refresh_at {
+                return Ok(tokens.clone());
+            }
+        }
+
+        let tokens = self.fetch_with_backoff().await?;
+
+        let _ = guard.insert(tokens.clone());
+
+        Ok(tokens)
+    }
+
+    /**
+     * Drops the cached session, the next call fetches a new one
+     */
+    pub async fn invalidate(&self) {
+        self.tokens.lock().await.take();
+    }
+
+    async fn fetch_with_backoff(&self) -> Result<Tokens, ResolverError> {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            match self.fetch().await {
+                Ok(tokens) => return Ok(tokens),
+                // retrying won't fix a bad arl
+                Err(error @ ResolverError::InvalidCredentials(_)) => return Err(error),
+                Err(error) if attempt >= MAX_ATTEMPTS => return Err(error),
+                Err(error) => {
+                    let backoff = BASE_BACKOFF * 2u32.pow(attempt - 1);
+
+                    tracing::warn!(
+                        "Failed to refresh the deezer session, retrying in {}ms [Attempt: {}/{}]: {}",
+                        backoff.as_millis(),
+                        attempt,
+                        MAX_ATTEMPTS,
+                        error
+                    );
+
+                    sleep(backoff).await;
+                }
+            }
+        }
+    }
+
+    async fn fetch(&self) -> Result<Tokens, ResolverError> {
+        let query = [
+            ("method", "deezer.getUserData"),
+            ("input", "3"),
+            ("api_version", "1.0"),
+            ("api_token", ""),
+        ];
+
+        let request = self
+            .client
+            .post(PRIVATE_API_BASE)
+            .header("Content-Length", "0")
+            .header("Cookie", format!("arl={}", self.arl))
+            .query(&query)
+            .build()?;
+
+        let response = self.client.execute(request).await?;
+
+        if !response.status().is_success() {
+            return Err(ResolverError::FailedStatusCode(
+                response.status().to_string(),
+            ));
+        }
+
+        let headers = response
+            .headers()
+            .get_all("Set-Cookie")
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .map(String::from)
+            .collect::<Vec<String>>();
+
+        let mut value = response.json::<Value>().await?;
+
+        // deezer answers with an anonymous user when the arl is wrong or expired
+        if value["results"]["USER"]["USER_ID"].as_i64().unwrap_or(0) == 0 {
+            return Err(ResolverError::InvalidCredentials("deezer"));
+        }
+
+        let data = serde_json::from_value::<InternalDeezerGetUserData>(value["results"].take())?;
+
+        let session_id = headers.iter().find(|str| str.starts_with("sid=")).ok_or(
+            ResolverError::MissingRequiredData("Missing Deezer Session Id"),
+        )?;
+
+        let unique_id = headers
+            .iter()
+            .find(|str| str.starts_with("dzr_uniq_id="))
+            .ok_or(ResolverError::MissingRequiredData(
+                "Missing Deezer Unique Id",
+            ))?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let refresh_in = refresh_in(data.user.options.expiration_timestamp, now).ok_or(
+            ResolverError::MissingRequiredData("Deezer returned an expired license"),
+        )?;
+
+        Ok(Tokens {
+            arl: self.arl.clone(),
+            session_id: session_id.to_string(),
+            unique_id: unique_id.to_string(),
+            check_form: data.check_form,
+            license_token: data.user.options.license_token,
+            refresh_at: Instant::now() + refresh_in,
+        })
+    }
+}
+
+/**
+ * How long a session can be used before refreshing it, None when the license already expired.
+ * The margin is capped at half the lifetime, so a license that is about to run out isn't refetched on every call
+ */
+fn refresh_in(expiration_timestamp: i64, now: u64) -> Option<Duration> {
+    // the license can run out before the session does
+    let license_lifetime = u64::try_from(expiration_timestamp)
+        .ok()?
+        .checked_sub(now)
+        .filter(|lifetime| *lifetime > 0)
+        .map(Duration::from_secs)?;
+
+    let lifetime = SESSION_LIFETIME.min(license_lifetime);
+
+    Some(lifetime - REFRESH_MARGIN.min(lifetime / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn long_licenses_refresh_with_the_session() {
+        assert_eq!(
+            refresh_in((NOW + 86_400) as i64, NOW),
+            Some(SESSION_LIFETIME - REFRESH_MARGIN)
+        );
+    }
+
+    #[test]
+    fn short_licenses_keep_half_their_lifetime() {
+        assert_eq!(
+            refresh_in((NOW + 30) as i64, NOW),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            refresh_in((NOW + 600) as i64, NOW),
+            Some(Duration::from_secs(540))
+        );
+    }
+
+    #[test]
+    fn expired_licenses_are_rejected() {
+        assert_eq!(refresh_in(NOW as i64, NOW), None);
+        assert_eq!(refresh_in((NOW - 60) as i64, NOW), None);
+        assert_eq!(refresh_in(-1, NOW), None);
+    }
+}
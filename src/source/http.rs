@@ -1,13 +1,15 @@
 use crate::{
-    models::{ApiTrack, ApiTrackInfo, ApiTrackResult, Empty},
+    models::{ApiTrack, ApiTrackInfo, ApiTrackResult, PluginInfo},
     util::{
         encoder::encode_base64,
         errors::ResolverError,
         seek::SeekableSource,
         source::{Query, Source},
-        url::is_url,
+        url::URL_REGEX,
     },
 };
+use async_trait::async_trait;
+use regex::Regex;
 use reqwest::Client;
 use songbird::{
     input::{AuxMetadata, Compose, HttpRequest, Input, LiveInput},
@@ -17,15 +19,11 @@ use std::{sync::Arc, time::Duration};
 
 pub struct Http {
     client: Client,
+    url_patterns: Vec<Regex>,
 }
 
+#[async_trait]
 impl Source for Http {
-    fn new(client: Option<Client>) -> Self {
-        Self {
-            client: client.unwrap_or_default(),
-        }
-    }
-
     fn get_name(&self) -> &'static str {
         "http"
     }
@@ -34,12 +32,17 @@ impl Source for Http {
         self.client.clone()
     }
 
-    fn parse_query(&self, query: &str) -> Option<Query> {
-        if !is_url(query) {
-            return None;
-        }
+    fn search_prefixes(&self) -> &[&'static str] {
+        &[]
+    }
 
-        Some(Query::Url(query.to_string()))
+    fn url_patterns(&self) -> &[Regex] {
+        &self.url_patterns
+    }
+
+    // checking the content type is cheap, so direct media urls don't wait for ytdlp
+    fn priority(&self) -> u8 {
+        1
     }
 
     async fn resolve(&self, query: Query) -> Result<ApiTrackResult, ResolverError> {
@@ -76,7 +79,7 @@ impl Source for Http {
         let track = ApiTrack {
             encoded: encode_base64(&info)?,
             info,
-            plugin_info: Empty,
+            plugin_info: PluginInfo::new(),
         };
 
         Ok(ApiTrackResult::Track(track))
@@ -106,6 +109,13 @@ impl Source for Http {
 }
 
 impl Http {
+    pub fn new(client: Option<Client>) -> Self {
+        Self {
+            client: client.unwrap_or_default(),
+            url_patterns: vec![URL_REGEX.clone()],
+        }
+    }
+
     fn make_track(&self, metadata: AuxMetadata) -> ApiTrackInfo {
         let identifier = metadata
             .source_url
@@ -1,3 +1,4 @@
 pub mod deezer;
 pub mod http;
 pub mod youtube;
+pub mod ytdlp;
@@ -1,104 +1,284 @@
 use crate::{
-    models::{ApiPlaylistInfo, ApiTrack, ApiTrackInfo, ApiTrackPlaylist, ApiTrackResult, Empty},
+    AvailableSources, Config,
+    models::{
+        ApiPlaylistInfo, ApiSearchResult, ApiSearchText, ApiSearchType, ApiTrack, ApiTrackInfo,
+        ApiTrackPlaylist, ApiTrackResult, PluginInfo,
+    },
     util::{
         encoder::encode_base64,
         errors::ResolverError,
+        hls::HlsStream,
         seek::SeekableSource,
         source::{Query, Source},
     },
 };
+use async_trait::async_trait;
+use base64::{
+    Engine,
+    prelude::{BASE64_URL_SAFE, BASE64_URL_SAFE_NO_PAD},
+};
 use bytesize::ByteSize;
+use dashmap::DashMap;
+use futures::{Stream, StreamExt, stream};
 use regex::Regex;
-use reqwest::Client;
+use reqwest::{
+    Client, StatusCode, Url,
+    header::{HeaderMap, HeaderValue, RANGE, USER_AGENT},
+};
 use rustypipe::{
-    client::{ClientType, RustyPipe},
-    model::{UrlTarget, VideoPlayer, YouTubeItem},
+    client::{ClientType, RustyPipe, RustyPipeQuery},
+    model::{
+        AudioCodec, MusicItem, TrackItem, UrlTarget, VideoItem, VideoPlayer, YouTubeItem,
+        paginator::Paginator,
+    },
     param::search_filter::{ItemType, SearchFilter},
 };
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use songbird::{
-    input::{Compose, HttpRequest, Input, LiveInput},
+    input::{AudioStream, AudioStreamError, Compose, HttpRequest, Input, LiveInput},
     tracks::Track,
 };
-use std::{fs, sync::Arc};
+use std::{
+    collections::HashSet,
+    fs,
+    io::{Error as IoError, ErrorKind},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use symphonia::core::io::MediaSource;
+use tokio::{runtime::Handle, task::block_in_place, time::Instant};
 
 static PROTOCOL_REGEX: &str = "(?:http://|https://|)";
 static DOMAIN_REGEX: &str = "(?:www\\.|m\\.|music\\.|)youtube\\.com";
 static SHORT_DOMAIN_REGEX: &str = "(?:www\\.|)youtu\\.be";
+static DEFAULT_CHANNEL_PAGE_LIMIT: usize = 1;
+static DEFAULT_PLAYLIST_TRACK_LIMIT: usize = 1000;
+static PLAYLIST_PAGE_CONCURRENCY: usize = 4;
+// autocomplete sends the same prefixes over and over while someone types
+static SEARCH_CACHE_TTL: Duration = Duration::from_secs(30);
 
-struct RegexList {
-    pub main_domain: Regex,
-    pub short_hand_domain: Regex,
+fn url_patterns() -> Vec<Regex> {
+    vec![
+        Regex::new(format!("^{PROTOCOL_REGEX}{DOMAIN_REGEX}/.*").as_str())
+            .expect("Failed to init main domain regex"),
+        Regex::new(
+            format!(
+                "^{PROTOCOL_REGEX}(?:{DOMAIN_REGEX}/(?:live|embed|shorts)|{SHORT_DOMAIN_REGEX})/(?<videoId>.*)"
+            )
+            .as_str(),
+        )
+        .expect("Failed to init short hand domain regex"),
+    ]
 }
 
-impl RegexList {
-    pub fn new() -> Self {
-        Self {
-            main_domain: Regex::new(format!("^{PROTOCOL_REGEX}{DOMAIN_REGEX}/.*").as_str())
-                .expect("Failed to init main domain regex"),
-            short_hand_domain: Regex::new(
-                format!(
-                    "^{PROTOCOL_REGEX}(?:{DOMAIN_REGEX}/(?:live|embed|shorts)|{SHORT_DOMAIN_REGEX})/(?<videoId>.*)"
-                )
-                .as_str(),
-            )
-            .expect("Failed to init short hand domain regex"),
-        }
+/**
+ * Unknown durations are reported like http streams of unknown length, u64::MAX and not seekable
+ */
+fn track_length(duration: Option<u32>) -> u64 {
+    duration
+        .map(|duration| duration as u64 * 1000)
+        .unwrap_or(u64::MAX)
+}
+
+/**
+ * Builds the continuation token YouTube uses for a playlist page starting at the given offset
+ */
+fn playlist_continuation(id: &str, offset: usize) -> String {
+    let position = BASE64_URL_SAFE_NO_PAD.encode(proto_varint(1, offset as u64));
+
+    let mut page = proto_varint(1, (offset / 100) as u64);
+    page.extend(proto_bytes(15, format!("PT:{position}").as_bytes()));
+
+    let mut browse = proto_bytes(2, format!("VL{id}").as_bytes());
+    browse.extend(proto_bytes(
+        3,
+        BASE64_URL_SAFE.encode(page).replace('=', "%3D").as_bytes(),
+    ));
+    browse.extend(proto_bytes(35, id.as_bytes()));
+
+    BASE64_URL_SAFE
+        .encode(proto_bytes(80226972, &browse))
+        .replace('=', "%3D")
+}
+
+/**
+ * Appends pages fetched with built continuations in order. A page that failed, came back empty or only repeats loaded videos
+ * means YouTube didn't understand the token, so this stops there and the caller carries on with the continuation of the last good page
+ */
+async fn append_offset_pages<S>(id: &str, videos: &mut Paginator<VideoItem>, mut pages: S)
+where
+    S: Stream<Item = Result<Paginator<VideoItem>, rustypipe::error::Error>> + Unpin,
+{
+    let mut loaded = videos
+        .items
+        .iter()
+        .map(|video| video.id.clone())
+        .collect::<HashSet<String>>();
+
+    while let Some(page) = pages.next().await {
+        let page = match page {
+            Ok(page) if page.items.iter().any(|video| !loaded.contains(&video.id)) => page,
+            Ok(_) => {
+                tracing::warn!(
+                    "A built continuation of [Playlist: {}] returned no new videos, continuing one page at a time",
+                    id
+                );
+                return;
+            }
+            Err(error) => {
+                tracing::debug!(
+                    "Failed to fetch a page of [Playlist: {}], continuing one page at a time: {}",
+                    id,
+                    error
+                );
+                return;
+            }
+        };
+
+        loaded.extend(page.items.iter().map(|video| video.id.clone()));
+
+        videos.items.extend(page.items);
+        videos.ctoken = page.ctoken;
     }
 }
 
-pub struct Youtube {
-    client: Client,
-    rusty_pipe: RustyPipe,
-    client_types: Vec<ClientType>,
-    video_itags: Vec<u32>,
-    audio_itags: Vec<u32>,
-    regex_list: RegexList,
+fn proto_varint(field: u64, value: u64) -> Vec<u8> {
+    let mut bytes = varint(field << 3);
+    bytes.extend(varint(value));
+    bytes
 }
 
-impl Source for Youtube {
-    fn new(client: Option<Client>) -> Self {
-        let mut rusty_pipe = RustyPipe::builder().n_http_retries(0);
+fn proto_bytes(field: u64, value: &[u8]) -> Vec<u8> {
+    let mut bytes = varint((field << 3) | 2);
+    bytes.extend(varint(value.len() as u64));
+    bytes.extend(value);
+    bytes
+}
 
-        if !fs::exists("./rustypipe").unwrap() {
-            fs::create_dir("./rustypipe").unwrap();
-        }
+fn varint(mut value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
 
-        if !fs::exists("./rustypipe/botguard").unwrap() {
-            fs::create_dir("./rustypipe/botguard").unwrap();
-        }
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
 
-        if fs::exists("./rustypipe/botguard/bin").unwrap() {
-            rusty_pipe = rusty_pipe
-                .po_token_cache()
-                .botguard_bin("./rustypipe/botguard/bin")
-                .botguard_snapshot_file("./rustypipe/botguard");
+    bytes.push(value as u8);
+    bytes
+}
 
-            tracing::info!("Youtube rustypipe-botguard (po_token) is set");
-        } else {
-            rusty_pipe = rusty_pipe.no_botguard();
+/**
+ * The http status a stream was rejected with. Songbird only wraps transport errors as they are and reports bad statuses as a message
+ */
+fn stream_status(error: &AudioStreamError) -> Option<StatusCode> {
+    let AudioStreamError::Fail(source) = error else {
+        return None;
+    };
 
-            tracing::warn!(
-                "The po_token feature was not enabled. The rustypipe-botguard was not found from './rustypipe/botguard' folder but po_cache setting was enabled. 
-                Please download one that is built for your system from 'https://codeberg.org/ThetaDev/rustypipe-botguard/releases', put it at ./rustypipe/botguard then rename it to 'bin'"
-            );
-        }
+    if let Some(error) = source.downcast_ref::<reqwest::Error>() {
+        return error.status();
+    }
 
-        rusty_pipe = rusty_pipe.storage_dir("./rustypipe/");
+    source
+        .to_string()
+        .strip_prefix("failed with http status code: ")?
+        .split_whitespace()
+        .next()?
+        .parse::<u16>()
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+}
 
-        Self {
-            client: client.unwrap_or_default(),
-            rusty_pipe: rusty_pipe.build().unwrap(),
-            client_types: vec![
-                ClientType::Desktop,
-                ClientType::DesktopMusic,
-                ClientType::Mobile,
-            ],
-            video_itags: vec![18, 22, 37, 44, 45, 46],
-            audio_itags: vec![140, 141, 171, 250, 251],
-            regex_list: RegexList::new(),
+fn query_param(url: &str, name: &str) -> Option<String> {
+    let url = if url.starts_with("http") {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("https://{url}"))
+    };
+
+    url.ok()?
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/**
+ * Search results only point at albums, artists and playlists, loading the url gets their tracks
+ */
+fn search_playlist(
+    name: String,
+    kind: &str,
+    url: String,
+    artwork_url: Option<String>,
+    author: Option<String>,
+    total_tracks: Option<u64>,
+) -> ApiTrackPlaylist {
+    let mut plugin_info = PluginInfo::new();
+
+    plugin_info.insert(String::from("type"), Value::from(kind));
+    plugin_info.insert(String::from("url"), Value::from(url));
+    plugin_info.insert(String::from("artworkUrl"), Value::from(artwork_url));
+    plugin_info.insert(String::from("author"), Value::from(author));
+    plugin_info.insert(String::from("totalTracks"), Value::from(total_tracks));
+
+    ApiTrackPlaylist {
+        info: ApiPlaylistInfo {
+            name,
+            selected_track: -1,
+        },
+        plugin_info,
+        tracks: Vec::new(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum YoutubeClient {
+    #[serde(rename = "DESKTOP")]
+    Desktop,
+    #[serde(rename = "DESKTOP_MUSIC")]
+    DesktopMusic,
+    #[serde(rename = "MOBILE")]
+    Mobile,
+    #[serde(rename = "TV")]
+    Tv,
+    #[serde(rename = "ANDROID")]
+    Android,
+    #[serde(rename = "IOS")]
+    Ios,
+}
+
+impl From<YoutubeClient> for ClientType {
+    fn from(client: YoutubeClient) -> Self {
+        match client {
+            YoutubeClient::Desktop => ClientType::Desktop,
+            YoutubeClient::DesktopMusic => ClientType::DesktopMusic,
+            YoutubeClient::Mobile => ClientType::Mobile,
+            YoutubeClient::Tv => ClientType::Tv,
+            YoutubeClient::Android => ClientType::Android,
+            YoutubeClient::Ios => ClientType::Ios,
         }
     }
+}
+
+pub struct Youtube {
+    client: Client,
+    rusty_pipe: RustyPipe,
+    client_types: Vec<ClientType>,
+    video_itags: Vec<u32>,
+    audio_itags: Vec<u32>,
+    url_patterns: Vec<Regex>,
+    search_cache: DashMap<String, (Instant, ApiSearchResult)>,
+    cookies: AtomicBool,
+    oauth: Arc<AtomicBool>,
+    po_token: bool,
+}
 
+#[async_trait]
+impl Source for Youtube {
     fn get_name(&self) -> &'static str {
         "youtube"
     }
@@ -107,23 +287,34 @@ impl Source for Youtube {
         self.client.clone()
     }
 
-    fn parse_query(&self, query: &str) -> Option<Query> {
-        if !self.regex_list.main_domain.is_match(query)
-            && !self.regex_list.short_hand_domain.is_match(query)
-        {
-            if query.starts_with("ytsearch") || query.starts_with("ytmsearch") {
-                return Some(Query::Search(query.to_string()));
-            } else {
-                return None;
-            }
-        }
+    fn search_prefixes(&self) -> &[&'static str] {
+        &["ytsearch:", "ytmsearch:", "ytrec:"]
+    }
 
-        Some(Query::Url(query.to_string()))
+    fn url_patterns(&self) -> &[Regex] {
+        &self.url_patterns
     }
 
     async fn resolve(&self, query: Query) -> Result<ApiTrackResult, ResolverError> {
         match query {
             Query::Url(url) => {
+                let is_music = url.contains("music.youtube.com");
+
+                if let Some(list) = query_param(&url, "list") {
+                    if list.starts_with("RD") {
+                        return self
+                            .resolve_mix(&list, query_param(&url, "v").as_deref())
+                            .await;
+                    }
+
+                    // a video opened from a playlist loads the whole playlist starting at that video
+                    if query_param(&url, "v").is_some() {
+                        return self.resolve_playlist(&list, &url).await;
+                    }
+                }
+
+                let source_url = url.clone();
+
                 let request = self.rusty_pipe.query().resolve_url(url, true).await?;
 
                 let request_url = request.to_url();
@@ -151,289 +342,1052 @@ impl Source for Youtube {
                         let track = ApiTrack {
                             encoded: encode_base64(&info)?,
                             info,
-                            plugin_info: Empty,
+                            plugin_info: PluginInfo::new(),
                         };
 
                         Ok(ApiTrackResult::Track(track))
                     }
-                    UrlTarget::Channel { .. } => Ok(ApiTrackResult::Empty(None)),
-                    UrlTarget::Playlist { id } => {
-                        let mut metadata = self.rusty_pipe.query().playlist(&id).await?;
-
-                        let mut playlist = ApiTrackPlaylist {
-                            info: ApiPlaylistInfo {
-                                name: metadata.name,
-                                selected_track: 0,
-                            },
-                            plugin_info: Empty,
-                            tracks: Vec::new(),
-                        };
-
-                        metadata
-                            .videos
-                            .extend_pages(self.rusty_pipe.query(), usize::MAX)
-                            .await?;
-
-                        for video in metadata.videos.items {
-                            let url = self
-                                .rusty_pipe
-                                .query()
-                                .resolve_string(&video.id, true)
-                                .await?;
-
-                            let info = ApiTrackInfo {
-                                identifier: video.id,
-                                is_seekable: !video.is_live,
-                                author: video
-                                    .channel
-                                    .map(|channel| channel.name)
-                                    .unwrap_or(String::from("Unknown")),
-                                length: video.duration.unwrap_or(u32::MAX) as u64,
-                                is_stream: video.is_live,
-                                position: 0,
-                                title: video.name,
-                                uri: Some(url.to_url()),
-                                artwork_url: video
-                                    .thumbnail
-                                    .first()
-                                    .map(|data| data.url.to_owned()),
-                                isrc: None,
-                                source_name: self.get_name().into(),
-                            };
-
-                            let track = ApiTrack {
-                                encoded: encode_base64(&info)?,
-                                info,
-                                plugin_info: Empty,
-                            };
-
-                            playlist.tracks.push(track);
-                        }
-
-                        Ok(ApiTrackResult::Playlist(playlist))
-                    }
-                    UrlTarget::Album { .. } => Ok(ApiTrackResult::Empty(None)),
+                    UrlTarget::Channel { id } => self.resolve_channel(&id, is_music).await,
+                    UrlTarget::Playlist { id } => self.resolve_playlist(&id, &source_url).await,
+                    UrlTarget::Album { id } => self.resolve_album(&id).await,
                 }
             }
             Query::Search(input) => {
+                if let Some(id) = input.strip_prefix("ytrec:") {
+                    return self.resolve_mix(&format!("RDAMVM{id}"), Some(id)).await;
+                }
+
+                if let Some(term) = input.strip_prefix("ytmsearch:") {
+                    let results = self.rusty_pipe.query().music_search_tracks(term).await?;
+
+                    let tracks = results
+                        .items
+                        .items
+                        .into_iter()
+                        .map(|item| self.make_music_track(item, None))
+                        .collect::<Result<Vec<ApiTrack>, ResolverError>>()?;
+
+                    return Ok(ApiTrackResult::Search(tracks));
+                }
+
                 let term = input
-                    .strip_prefix("ytsearch")
-                    .or(input.strip_prefix("ytmsearch"))
+                    .strip_prefix("ytsearch:")
                     .ok_or(ResolverError::InputNotSupported)?;
 
-                let (prefix, _) = input.split_at(term.len() - input.len());
-
-                match prefix {
-                    "ytsearch" => {
-                        let filter = SearchFilter::new().item_type(ItemType::Video);
-
-                        let results = self
-                            .rusty_pipe
-                            .query()
-                            .search_filter::<YouTubeItem, _>(term, &filter)
-                            .await?;
-
-                        let mut tracks = Vec::new();
-
-                        for result in results.items.items {
-                            match result {
-                                YouTubeItem::Video(video) => {
-                                    let info = ApiTrackInfo {
-                                        identifier: video.id.to_owned(),
-                                        is_seekable: !video.is_live,
-                                        author: video
-                                            .channel
-                                            .map(|channel| channel.name)
-                                            .unwrap_or(String::from("Unknown")),
-                                        length: video.duration.unwrap_or(u32::MAX) as u64,
-                                        is_stream: video.is_live,
-                                        position: 0,
-                                        title: video.name,
-                                        uri: Some(format!(
-                                            "https://www.youtube.com/watch?{}",
-                                            video.id
-                                        )),
-                                        artwork_url: video
-                                            .thumbnail
-                                            .first()
-                                            .map(|data| data.url.to_owned()),
-                                        isrc: None,
-                                        source_name: self.get_name().into(),
-                                    };
-
-                                    let track = ApiTrack {
-                                        encoded: encode_base64(&info)?,
-                                        info,
-                                        plugin_info: Empty,
-                                    };
-
-                                    tracks.push(track);
-                                }
-                                _ => return Err(ResolverError::MissingRequiredData("Video Item")),
-                            }
-                        }
+                let filter = SearchFilter::new().item_type(ItemType::Video);
 
-                        Ok(ApiTrackResult::Search(tracks))
-                    }
-                    "ytmsearch" => {
-                        let results = self.rusty_pipe.query().music_search_videos(term).await?;
-
-                        let mut tracks = Vec::new();
-
-                        for result in results.items.items {
-                            let info = ApiTrackInfo {
-                                identifier: result.id.to_owned(),
-                                is_seekable: true,
-                                author: result
-                                    .artists
-                                    .first()
-                                    .map(|artist| artist.name.to_owned())
-                                    .unwrap_or(String::from("Unknown")),
-                                length: result.duration.unwrap_or(0) as u64,
-                                is_stream: result.duration.map(|_| true).unwrap_or(false),
-                                position: 0,
-                                title: result.name,
-                                uri: Some(format!(
-                                    "https://music.youtube.com/watch?v={}",
-                                    result.id
-                                )),
-                                artwork_url: result.cover.first().map(|data| data.url.to_owned()),
-                                isrc: None,
-                                source_name: self.get_name().into(),
-                            };
-
-                            let track = ApiTrack {
-                                encoded: encode_base64(&info)?,
-                                info,
-                                plugin_info: Empty,
-                            };
-
-                            tracks.push(track);
-                        }
-
-                        Ok(ApiTrackResult::Search(tracks))
+                let results = self
+                    .rusty_pipe
+                    .query()
+                    .search_filter::<YouTubeItem, _>(term, &filter)
+                    .await?;
+
+                let mut tracks = Vec::new();
+
+                for result in results.items.items {
+                    match result {
+                        YouTubeItem::Video(video) => tracks.push(self.make_video_track(video)?),
+                        _ => return Err(ResolverError::MissingRequiredData("Video Item")),
                     }
-                    _ => Err(ResolverError::InputNotSupported),
                 }
+
+                Ok(ApiTrackResult::Search(tracks))
             }
         }
     }
 
+    async fn load_search(
+        &self,
+        query: &str,
+        types: &[ApiSearchType],
+    ) -> Result<Option<ApiSearchResult>, ResolverError> {
+        let key = format!("{query}:{types:?}");
+
+        if let Some(entry) = self.search_cache.get(&key) {
+            if entry.0.elapsed() < SEARCH_CACHE_TTL {
+                return Ok(Some(entry.1.clone()));
+            }
+        }
+
+        let result = if let Some(term) = query.strip_prefix("ytmsearch:") {
+            self.load_music_search(term, types).await?
+        } else if let Some(term) = query.strip_prefix("ytsearch:") {
+            self.load_video_search(term, types).await?
+        } else {
+            return Ok(None);
+        };
+
+        self.search_cache
+            .retain(|_, (cached_at, _)| cached_at.elapsed() < SEARCH_CACHE_TTL);
+        self.search_cache
+            .insert(key, (Instant::now(), result.clone()));
+
+        Ok(Some(result))
+    }
+
     async fn make_playable(&self, track: ApiTrack) -> Result<Track, ResolverError> {
-        let player = {
-            let mut result: Option<VideoPlayer> = None;
+        let stream = self.open_stream(&track.info.identifier, None).await?;
 
-            for client in &self.client_types {
-                let video = self
-                    .rusty_pipe
-                    .query()
-                    .player_from_client(&track.info.identifier, *client)
-                    .await;
-
-                let client_name = format!("Client [{}]", self.readable_client_type(client));
-
-                match video {
-                    Ok(video) => {
-                        if video.audio_streams.is_empty() && video.video_streams.is_empty() {
-                            tracing::warn!(
-                                "{} failed to get results due to: No streams available",
-                                client_name,
-                            );
-                            continue;
-                        }
-
-                        tracing::info!(
-                            "{} got results! Formats Count => [Audio: {}]  [Video: {}]",
-                            client_name,
-                            video.audio_streams.len(),
-                            video.video_streams.len()
+        let handle = Handle::current();
+        let name = self.get_name();
+        let refresh_track = track.clone();
+
+        // stream urls expire after a few hours, this asks whichever youtube source is registered for a new one
+        let seekable = SeekableSource::new(stream.input).with_refresh(Box::new(move |offset| {
+            let source = AvailableSources
+                .get(name)
+                .map(|source| source.clone())
+                .ok_or_else(|| IoError::new(ErrorKind::NotFound, "Youtube is not available"))?;
+
+            block_in_place(|| handle.block_on(source.refresh_stream(&refresh_track, offset)))
+                .map_err(IoError::other)?
+                .ok_or_else(|| IoError::new(ErrorKind::Unsupported, "Stream can't be refreshed"))
+        }));
+
+        let input = Input::Live(
+            LiveInput::Raw(seekable.into_audio_stream(stream.hint)),
+            None,
+        );
+
+        Ok(Track::new_with_data(input, Arc::new(track)))
+    }
+
+    async fn refresh_stream(
+        &self,
+        track: &ApiTrack,
+        offset: usize,
+    ) -> Result<Option<Box<dyn MediaSource>>, ResolverError> {
+        tracing::info!(
+            "Refreshing the stream of [{}] at [{}]",
+            track.info.identifier,
+            ByteSize::b(offset as u64).display().iec_short()
+        );
+
+        let stream = self
+            .open_stream(&track.info.identifier, Some(offset))
+            .await?;
+
+        Ok(Some(stream.input))
+    }
+}
+
+impl Youtube {
+    pub fn new(client: Option<Client>) -> Self {
+        let mut rusty_pipe = RustyPipe::builder().n_http_retries(0);
+
+        if !fs::exists("./rustypipe").unwrap() {
+            fs::create_dir("./rustypipe").unwrap();
+        }
+
+        if !fs::exists("./rustypipe/botguard").unwrap() {
+            fs::create_dir("./rustypipe/botguard").unwrap();
+        }
+
+        let config = Config.load();
+        let youtube_config = config.youtube_config.as_ref();
+
+        let use_po_token = youtube_config
+            .and_then(|config| config.use_po_token)
+            .unwrap_or(true);
+
+        let po_token = use_po_token && fs::exists("./rustypipe/botguard/bin").unwrap();
+
+        if po_token {
+            rusty_pipe = rusty_pipe
+                .po_token_cache()
+                .botguard_bin("./rustypipe/botguard/bin")
+                .botguard_snapshot_file("./rustypipe/botguard");
+
+            tracing::info!("Youtube rustypipe-botguard (po_token) is set");
+        } else {
+            rusty_pipe = rusty_pipe.no_botguard();
+
+            if use_po_token {
+                tracing::warn!(
+                    "The po_token feature was not enabled. The rustypipe-botguard was not found from './rustypipe/botguard' folder but po_cache setting was enabled. 
+                Please download one that is built for your system from 'https://codeberg.org/ThetaDev/rustypipe-botguard/releases', put it at ./rustypipe/botguard then rename it to 'bin'"
+                );
+            }
+        }
+
+        rusty_pipe = rusty_pipe.storage_dir("./rustypipe/");
+
+        Self {
+            client: client.unwrap_or_default(),
+            rusty_pipe: rusty_pipe.build().unwrap(),
+            client_types: youtube_config
+                .and_then(|config| config.clients.as_ref())
+                .map(|clients| {
+                    clients
+                        .iter()
+                        .map(|client| ClientType::from(*client))
+                        .collect()
+                })
+                .unwrap_or(vec![
+                    ClientType::Desktop,
+                    ClientType::DesktopMusic,
+                    ClientType::Mobile,
+                ]),
+            video_itags: youtube_config
+                .and_then(|config| config.video_itags.clone())
+                .unwrap_or(vec![18, 22, 37, 44, 45, 46]),
+            audio_itags: youtube_config
+                .and_then(|config| config.audio_itags.clone())
+                .unwrap_or(vec![140, 141, 171, 250, 251]),
+            url_patterns: url_patterns(),
+            search_cache: DashMap::new(),
+            cookies: AtomicBool::new(false),
+            oauth: Arc::new(AtomicBool::new(false)),
+            po_token,
+        }
+    }
+
+    /**
+     * Applies the configured cookies and oauth login. An oauth login that isn't stored yet finishes in the background
+     */
+    pub async fn init(&self) {
+        let config = Config.load();
+
+        let Some(youtube_config) = config.youtube_config.as_ref() else {
+            return;
+        };
+
+        let mut cookies_mode = "disabled";
+        let mut oauth_mode = "disabled";
+
+        if let Some(cookies) = youtube_config
+            .cookies
+            .as_ref()
+            .filter(|cookies| !cookies.trim().is_empty())
+        {
+            // a path to a cookies.txt file works as well as a raw cookie header
+            let result = match fs::read_to_string(cookies) {
+                Ok(file) => self.rusty_pipe.user_auth_set_cookie_txt(&file).await,
+                Err(_) => self.rusty_pipe.user_auth_set_cookie(cookies.as_str()).await,
+            };
+
+            match result {
+                Ok(()) => {
+                    self.cookies.store(true, Ordering::Relaxed);
+                    cookies_mode = "enabled";
+                }
+                Err(error) => {
+                    tracing::error!("Failed to apply the youtube cookies: {}", error);
+                    cookies_mode = "failed";
+                }
+            }
+        }
+
+        if youtube_config.use_oauth.unwrap_or(false) {
+            if self.rusty_pipe.user_auth_check_login().await.is_ok() {
+                self.oauth.store(true, Ordering::Relaxed);
+                oauth_mode = "enabled";
+            } else {
+                match self.rusty_pipe.user_auth_get_code().await {
+                    Ok(code) => {
+                        tracing::warn!(
+                            "Youtube OAuth needs a login, open {} and enter [{}] within {}s. The token is stored in './rustypipe' afterwards",
+                            code.verification_url,
+                            code.user_code,
+                            code.expires_in
                         );
 
-                        let _ = result.insert(video);
+                        let rusty_pipe = self.rusty_pipe.clone();
+                        let oauth = self.oauth.clone();
 
-                        break;
+                        tokio::spawn(async move {
+                            match rusty_pipe.user_auth_wait_for_login(&code).await {
+                                Ok(()) => {
+                                    oauth.store(true, Ordering::Relaxed);
+                                    tracing::info!("Youtube OAuth login completed");
+                                }
+                                Err(error) => {
+                                    tracing::error!("Youtube OAuth login failed: {}", error)
+                                }
+                            }
+                        });
+
+                        oauth_mode = "pending";
                     }
-                    Err(err) => {
-                        tracing::warn!("{} failed to get results due to: {:?}", client_name, err);
+                    Err(error) => {
+                        tracing::error!("Failed to start the youtube OAuth login: {}", error);
+                        oauth_mode = "failed";
                     }
                 }
             }
 
-            result.ok_or(ResolverError::MissingRequiredData(
-                "Failed to resolve an Api Track",
-            ))?
+            if !self.client_types.contains(&ClientType::Tv) {
+                tracing::warn!(
+                    "Youtube OAuth only applies to the TV client, add it to youtubeConfig.clients to use it"
+                );
+            }
+        }
+
+        tracing::info!(
+            "Youtube auth [Cookies: {}] [OAuth: {}] [PO Token: {}]",
+            cookies_mode,
+            oauth_mode,
+            if self.po_token { "enabled" } else { "disabled" }
+        );
+    }
+
+    /**
+     * Cookies authenticate the browser clients and oauth the tv client, other clients stay anonymous
+     */
+    fn query_for(&self, client: ClientType) -> RustyPipeQuery {
+        let query = self.rusty_pipe.query();
+
+        let authenticated = match client {
+            ClientType::Desktop | ClientType::DesktopMusic | ClientType::Mobile => {
+                self.cookies.load(Ordering::Relaxed)
+            }
+            ClientType::Tv => self.oauth.load(Ordering::Relaxed),
+            _ => false,
         };
 
-        let audio = player
-            .audio_streams
-            .iter()
-            .filter(|stream| self.audio_itags.contains(&stream.itag))
-            .reduce(|prev, current| {
-                if prev.bitrate > current.bitrate {
-                    prev
+        if authenticated {
+            query.authenticated()
+        } else {
+            query
+        }
+    }
+
+    async fn load_music_search(
+        &self,
+        term: &str,
+        types: &[ApiSearchType],
+    ) -> Result<ApiSearchResult, ResolverError> {
+        let query = self.rusty_pipe.query();
+
+        let (items, suggestion) = tokio::join!(
+            async {
+                if types.iter().any(|kind| *kind != ApiSearchType::Text) {
+                    query.music_search_main(term).await.map(Some)
+                } else {
+                    Ok(None)
+                }
+            },
+            async {
+                if types.contains(&ApiSearchType::Text) {
+                    query.music_search_suggestion(term).await.map(Some)
                 } else {
-                    current
+                    Ok(None)
+                }
+            }
+        );
+
+        let mut result = ApiSearchResult::default();
+
+        for item in items?.map(|items| items.items.items).unwrap_or_default() {
+            match item {
+                MusicItem::Track(track) if types.contains(&ApiSearchType::Track) => {
+                    result.tracks.push(self.make_music_track(track, None)?);
+                }
+                MusicItem::Album(album) if types.contains(&ApiSearchType::Album) => {
+                    result.albums.push(search_playlist(
+                        album.name,
+                        "album",
+                        format!("https://music.youtube.com/browse/{}", album.id),
+                        album.cover.first().map(|data| data.url.to_owned()),
+                        album.artists.first().map(|artist| artist.name.to_owned()),
+                        None,
+                    ));
+                }
+                MusicItem::Artist(artist) if types.contains(&ApiSearchType::Artist) => {
+                    result.artists.push(search_playlist(
+                        format!("{}'s Top Tracks", artist.name),
+                        "artist",
+                        format!("https://music.youtube.com/channel/{}", artist.id),
+                        artist.avatar.first().map(|data| data.url.to_owned()),
+                        Some(artist.name),
+                        None,
+                    ));
                 }
+                MusicItem::Playlist(playlist) if types.contains(&ApiSearchType::Playlist) => {
+                    result.playlists.push(search_playlist(
+                        playlist.name,
+                        "playlist",
+                        format!("https://music.youtube.com/playlist?list={}", playlist.id),
+                        playlist.thumbnail.first().map(|data| data.url.to_owned()),
+                        playlist.channel.map(|channel| channel.name),
+                        playlist.track_count,
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        for text in suggestion?
+            .map(|suggestion| suggestion.terms)
+            .unwrap_or_default()
+        {
+            result.texts.push(ApiSearchText {
+                text,
+                plugin: PluginInfo::new(),
             });
+        }
+
+        Ok(result)
+    }
+
+    async fn load_video_search(
+        &self,
+        term: &str,
+        types: &[ApiSearchType],
+    ) -> Result<ApiSearchResult, ResolverError> {
+        let query = self.rusty_pipe.query();
+
+        let (items, suggestion) = tokio::join!(
+            async {
+                if types.iter().any(|kind| *kind != ApiSearchType::Text) {
+                    query.search::<YouTubeItem, _>(term).await.map(Some)
+                } else {
+                    Ok(None)
+                }
+            },
+            async {
+                if types.contains(&ApiSearchType::Text) {
+                    query.search_suggestion(term).await.map(Some)
+                } else {
+                    Ok(None)
+                }
+            }
+        );
+
+        let mut result = ApiSearchResult::default();
+
+        for item in items?.map(|items| items.items.items).unwrap_or_default() {
+            match item {
+                YouTubeItem::Video(video) if types.contains(&ApiSearchType::Track) => {
+                    result.tracks.push(self.make_video_track(video)?);
+                }
+                YouTubeItem::Channel(channel) if types.contains(&ApiSearchType::Artist) => {
+                    result.artists.push(search_playlist(
+                        format!("{}'s Uploads", channel.name),
+                        "channel",
+                        format!("https://www.youtube.com/channel/{}", channel.id),
+                        channel.avatar.first().map(|data| data.url.to_owned()),
+                        Some(channel.name),
+                        None,
+                    ));
+                }
+                YouTubeItem::Playlist(playlist) if types.contains(&ApiSearchType::Playlist) => {
+                    result.playlists.push(search_playlist(
+                        playlist.name,
+                        "playlist",
+                        format!("https://www.youtube.com/playlist?list={}", playlist.id),
+                        playlist.thumbnail.first().map(|data| data.url.to_owned()),
+                        playlist.channel.map(|channel| channel.name),
+                        playlist.video_count,
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        for text in suggestion?.unwrap_or_default() {
+            result.texts.push(ApiSearchText {
+                text,
+                plugin: PluginInfo::new(),
+            });
+        }
+
+        Ok(result)
+    }
+
+    /**
+     * Mixes come from the YouTube Music radio, videos without one fall back to the related videos from the watch page
+     */
+    async fn resolve_mix(
+        &self,
+        radio_id: &str,
+        video_id: Option<&str>,
+    ) -> Result<ApiTrackResult, ResolverError> {
+        let tracks = match self.rusty_pipe.query().music_radio(radio_id).await {
+            Ok(radio) => radio
+                .items
+                .into_iter()
+                .map(|item| self.make_music_track(item, None))
+                .collect::<Result<Vec<ApiTrack>, ResolverError>>()?,
+            Err(error) => {
+                let Some(video_id) = video_id else {
+                    return Err(error.into());
+                };
+
+                tracing::debug!(
+                    "No radio for [{}], using related videos instead: {}",
+                    video_id,
+                    error
+                );
+
+                let details = self.rusty_pipe.query().video_details(video_id).await?;
+
+                details
+                    .recommended
+                    .items
+                    .into_iter()
+                    .map(|video| self.make_video_track(video))
+                    .collect::<Result<Vec<ApiTrack>, ResolverError>>()?
+            }
+        };
+
+        if tracks.is_empty() {
+            return Ok(ApiTrackResult::Empty(None));
+        }
+
+        let selected_track =
+            video_id.and_then(|id| tracks.iter().position(|track| track.info.identifier == id));
+
+        let seed = selected_track
+            .and_then(|index| tracks.get(index))
+            .or(tracks.first())
+            .map(|track| track.info.title.to_owned())
+            .unwrap_or_default();
+
+        let mut plugin_info = PluginInfo::new();
+
+        plugin_info.insert(String::from("type"), Value::from("mix"));
+        plugin_info.insert(String::from("mixId"), Value::from(radio_id));
+
+        Ok(ApiTrackResult::Playlist(ApiTrackPlaylist {
+            info: ApiPlaylistInfo {
+                name: format!("YouTube Mix: {seed}"),
+                selected_track: selected_track.map(|index| index as i32).unwrap_or(-1),
+            },
+            plugin_info,
+            tracks,
+        }))
+    }
+
+    /**
+     * Pages after the first are requested together with continuation tokens made for their offset.
+     * Whatever they don't cover, e.g. after a failed page, is picked up by following the regular continuation tokens
+     */
+    async fn resolve_playlist(&self, id: &str, url: &str) -> Result<ApiTrackResult, ResolverError> {
+        let track_limit = Config
+            .load()
+            .youtube_config
+            .as_ref()
+            .and_then(|config| config.playlist_track_limit)
+            .unwrap_or(DEFAULT_PLAYLIST_TRACK_LIMIT);
+
+        let mut query = self.rusty_pipe.query();
+
+        let mut metadata = query.playlist(id).await?;
+
+        if metadata.videos.authenticated {
+            query = query.authenticated();
+        }
+
+        let videos = &mut metadata.videos;
+        let page_size = videos.items.len();
+        let total = videos
+            .count
+            .map(|count| count as usize)
+            .unwrap_or_default()
+            .min(track_limit);
+
+        if page_size > 0 && videos.ctoken.is_some() {
+            let endpoint = videos.endpoint;
+            let visitor_data = videos.visitor_data.clone();
+
+            let pages = stream::iter((page_size..total).step_by(page_size))
+                .map(|offset| {
+                    query.continuation::<VideoItem, _>(
+                        playlist_continuation(id, offset),
+                        endpoint,
+                        visitor_data.as_deref(),
+                    )
+                })
+                .buffered(PLAYLIST_PAGE_CONCURRENCY);
+
+            append_offset_pages(id, videos, pages).await;
+        }
+
+        videos.extend_limit(&query, track_limit).await?;
+
+        let tracks = metadata
+            .videos
+            .items
+            .into_iter()
+            .take(track_limit)
+            .map(|video| self.make_video_track(video))
+            .collect::<Result<Vec<ApiTrack>, ResolverError>>()?;
+
+        if tracks.is_empty() {
+            return Ok(ApiTrackResult::Empty(None));
+        }
+
+        // index is 1 based and wins over v when both are present
+        let selected_track = query_param(url, "index")
+            .and_then(|index| index.parse::<usize>().ok())
+            .and_then(|index| index.checked_sub(1))
+            .filter(|index| *index < tracks.len())
+            .or_else(|| {
+                let video_id = query_param(url, "v")?;
+
+                tracks
+                    .iter()
+                    .position(|track| track.info.identifier == video_id)
+            })
+            .map(|index| index as i32)
+            .unwrap_or(-1);
+
+        Ok(ApiTrackResult::Playlist(ApiTrackPlaylist {
+            info: ApiPlaylistInfo {
+                name: metadata.name,
+                selected_track,
+            },
+            plugin_info: PluginInfo::new(),
+            tracks,
+        }))
+    }
+
+    fn make_video_track(&self, video: VideoItem) -> Result<ApiTrack, ResolverError> {
+        let info = ApiTrackInfo {
+            identifier: video.id.to_owned(),
+            is_seekable: video.duration.is_some(),
+            author: video
+                .channel
+                .map(|channel| channel.name)
+                .unwrap_or(String::from("Unknown")),
+            length: track_length(video.duration),
+            is_stream: video.duration.is_none(),
+            position: 0,
+            title: video.name,
+            uri: Some(format!("https://www.youtube.com/watch?v={}", video.id)),
+            artwork_url: video.thumbnail.first().map(|data| data.url.to_owned()),
+            isrc: None,
+            source_name: self.get_name().into(),
+        };
+
+        Ok(ApiTrack {
+            encoded: encode_base64(&info)?,
+            info,
+            plugin_info: PluginInfo::new(),
+        })
+    }
+
+    /**
+     * Albums come with all of their tracks in a single request
+     */
+    async fn resolve_album(&self, id: &str) -> Result<ApiTrackResult, ResolverError> {
+        let album = self.rusty_pipe.query().music_album(id).await?;
+
+        if album.tracks.is_empty() {
+            return Ok(ApiTrackResult::Empty(None));
+        }
+
+        let artwork_url = album.cover.first().map(|data| data.url.to_owned());
+        let album_url = format!("https://music.youtube.com/browse/{}", album.id);
+
+        let mut tracks = Vec::new();
+
+        for item in album.tracks {
+            let track_number = item.track_nr;
+
+            let mut track = self.make_music_track(item, artwork_url.clone())?;
+
+            track
+                .plugin_info
+                .insert(String::from("albumName"), Value::from(album.name.clone()));
+            track
+                .plugin_info
+                .insert(String::from("albumUrl"), Value::from(album_url.clone()));
+
+            if let Some(track_number) = track_number {
+                track
+                    .plugin_info
+                    .insert(String::from("trackNumber"), Value::from(track_number));
+            }
+
+            tracks.push(track);
+        }
+
+        let mut plugin_info = PluginInfo::new();
+
+        plugin_info.insert(String::from("type"), Value::from("album"));
+        plugin_info.insert(String::from("url"), Value::from(album_url));
+        plugin_info.insert(
+            String::from("artist"),
+            Value::from(
+                album
+                    .artists
+                    .iter()
+                    .map(|artist| artist.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+            ),
+        );
+        plugin_info.insert(String::from("artistId"), Value::from(album.artist_id));
+        plugin_info.insert(
+            String::from("release"),
+            serde_json::to_value(album.album_type)?,
+        );
+        plugin_info.insert(String::from("year"), Value::from(album.year));
+        plugin_info.insert(String::from("artworkUrl"), Value::from(artwork_url));
+        plugin_info.insert(String::from("totalTracks"), Value::from(album.track_count));
+
+        Ok(ApiTrackResult::Playlist(ApiTrackPlaylist {
+            info: ApiPlaylistInfo {
+                name: album.name,
+                selected_track: -1,
+            },
+            plugin_info,
+            tracks,
+        }))
+    }
+
+    /**
+     * Music channels resolve to the artist's top tracks, other channels to their latest uploads
+     */
+    async fn resolve_channel(
+        &self,
+        id: &str,
+        is_music: bool,
+    ) -> Result<ApiTrackResult, ResolverError> {
+        let mut plugin_info = PluginInfo::new();
+
+        plugin_info.insert(String::from("type"), Value::from("channel"));
+        plugin_info.insert(String::from("channelId"), Value::from(id));
+
+        let (name, tracks) = if is_music {
+            let artist = self.rusty_pipe.query().music_artist(id, false).await?;
+
+            plugin_info.insert(
+                String::from("url"),
+                Value::from(format!("https://music.youtube.com/channel/{id}")),
+            );
+            plugin_info.insert(
+                String::from("artworkUrl"),
+                Value::from(artist.header_image.first().map(|data| data.url.to_owned())),
+            );
+
+            let tracks = artist
+                .tracks
+                .into_iter()
+                .map(|item| self.make_music_track(item, None))
+                .collect::<Result<Vec<ApiTrack>, ResolverError>>()?;
+
+            (format!("{}'s Top Tracks", artist.name), tracks)
+        } else {
+            let mut channel = self.rusty_pipe.query().channel_videos(id).await?;
+
+            let page_limit = Config
+                .load()
+                .youtube_config
+                .as_ref()
+                .and_then(|config| config.channel_page_limit)
+                .unwrap_or(DEFAULT_CHANNEL_PAGE_LIMIT);
+
+            channel
+                .content
+                .extend_pages(self.rusty_pipe.query(), page_limit.saturating_sub(1))
+                .await?;
+
+            plugin_info.insert(
+                String::from("url"),
+                Value::from(format!("https://www.youtube.com/channel/{id}")),
+            );
+            plugin_info.insert(
+                String::from("artworkUrl"),
+                Value::from(channel.avatar.first().map(|data| data.url.to_owned())),
+            );
+
+            let mut tracks = Vec::new();
+
+            for video in channel.content.items {
+                if video.is_upcoming {
+                    continue;
+                }
+
+                let info = ApiTrackInfo {
+                    identifier: video.id.to_owned(),
+                    is_seekable: video.duration.is_some(),
+                    author: video
+                        .channel
+                        .map(|channel| channel.name)
+                        .unwrap_or(channel.name.to_owned()),
+                    length: track_length(video.duration),
+                    is_stream: video.duration.is_none(),
+                    position: 0,
+                    title: video.name,
+                    uri: Some(format!("https://www.youtube.com/watch?v={}", video.id)),
+                    artwork_url: video.thumbnail.first().map(|data| data.url.to_owned()),
+                    isrc: None,
+                    source_name: self.get_name().into(),
+                };
+
+                tracks.push(ApiTrack {
+                    encoded: encode_base64(&info)?,
+                    info,
+                    plugin_info: PluginInfo::new(),
+                });
+            }
+
+            (format!("{}'s Uploads", channel.name), tracks)
+        };
+
+        if tracks.is_empty() {
+            return Ok(ApiTrackResult::Empty(None));
+        }
+
+        plugin_info.insert(String::from("author"), Value::from(name.clone()));
+
+        Ok(ApiTrackResult::Playlist(ApiTrackPlaylist {
+            info: ApiPlaylistInfo {
+                name,
+                selected_track: -1,
+            },
+            plugin_info,
+            tracks,
+        }))
+    }
+
+    fn make_music_track(
+        &self,
+        item: TrackItem,
+        artwork_url: Option<String>,
+    ) -> Result<ApiTrack, ResolverError> {
+        let info = ApiTrackInfo {
+            identifier: item.id.to_owned(),
+            is_seekable: item.duration.is_some(),
+            author: item
+                .artists
+                .iter()
+                .map(|artist| artist.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", "),
+            length: track_length(item.duration),
+            is_stream: item.duration.is_none(),
+            position: 0,
+            title: item.name,
+            uri: Some(format!("https://music.youtube.com/watch?v={}", item.id)),
+            artwork_url: item
+                .cover
+                .first()
+                .map(|data| data.url.to_owned())
+                .or(artwork_url),
+            // youtube music doesn't expose isrcs, neither in search results nor in track details
+            isrc: None,
+            source_name: self.get_name().into(),
+        };
+
+        let mut plugin_info = PluginInfo::new();
+
+        if let Some(album) = item.album {
+            plugin_info.insert(String::from("albumName"), Value::from(album.name));
+            plugin_info.insert(
+                String::from("albumUrl"),
+                Value::from(format!("https://music.youtube.com/browse/{}", album.id)),
+            );
+        }
+
+        if let Some(artist_id) = item.artist_id {
+            plugin_info.insert(
+                String::from("artistUrl"),
+                Value::from(format!("https://music.youtube.com/channel/{artist_id}")),
+            );
+        }
+
+        Ok(ApiTrack {
+            encoded: encode_base64(&info)?,
+            info,
+            plugin_info,
+        })
+    }
+
+    /**
+     * Tries the configured clients in order until one gives a stream that opens, starting at the offset when given
+     */
+    async fn open_stream(
+        &self,
+        id: &str,
+        offset: Option<usize>,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, ResolverError> {
+        let mut error = None;
+
+        for client in &self.client_types {
+            let client_name = format!("Client [{}]", self.readable_client_type(client));
+
+            let player = match self
+                .query_for(*client)
+                .player_from_client(id, *client)
+                .await
+            {
+                Ok(player) => player,
+                Err(err) => {
+                    tracing::warn!("{} failed to get results due to: {:?}", client_name, err);
+                    continue;
+                }
+            };
+
+            if player.details.is_live {
+                let Some(manifest_url) = player.hls_manifest_url.as_deref() else {
+                    tracing::warn!(
+                        "{} failed to get results due to: No HLS manifest for the livestream",
+                        client_name,
+                    );
+                    continue;
+                };
+
+                match self.open_live_stream(&player, manifest_url).await {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => {
+                        tracing::warn!(
+                            "{} failed to follow the livestream due to: {}",
+                            client_name,
+                            err
+                        );
+
+                        error = Some(err);
+                        continue;
+                    }
+                }
+            }
+
+            if player.audio_streams.is_empty() && player.video_streams.is_empty() {
+                tracing::warn!(
+                    "{} failed to get results due to: No streams available",
+                    client_name,
+                );
+                continue;
+            }
 
-        if let Some(stream) = audio {
             tracing::info!(
-                "Picked [{}] [{} ({}/s)] for the playback",
-                stream.itag,
-                stream.mime,
-                ByteSize::b(stream.bitrate as u64).display().iec_short()
+                "{} got results! Formats Count => [Audio: {}]  [Video: {}]",
+                client_name,
+                player.audio_streams.len(),
+                player.video_streams.len()
             );
+
+            let Some((url, size)) = self.pick_stream(&player) else {
+                tracing::warn!(
+                    "{} failed to get results due to: No streams matching the configured itags",
+                    client_name,
+                );
+                continue;
+            };
+
+            let mut headers = HeaderMap::new();
+
+            if let Some(offset) = offset {
+                // googlevideo wants the end of the range as well
+                let range = match size {
+                    Some(size) => format!("bytes={offset}-{}", size.saturating_sub(1)),
+                    None => format!("bytes={offset}-"),
+                };
+
+                if let Ok(range) = HeaderValue::from_str(&range) {
+                    headers.insert(RANGE, range);
+                }
+            }
+
+            let mut request = HttpRequest::new_with_headers(self.get_client(), url, headers);
+
+            match request.create_async().await {
+                Ok(stream) => return Ok(stream),
+                // a stream url can be forbidden or gone for one client and work for another
+                Err(err)
+                    if matches!(
+                        stream_status(&err),
+                        Some(StatusCode::FORBIDDEN | StatusCode::GONE)
+                    ) =>
+                {
+                    tracing::warn!(
+                        "{} got a rejected stream, trying the next client",
+                        client_name
+                    );
+
+                    error = Some(err.into());
+                }
+                Err(err) => return Err(err.into()),
+            }
         }
 
-        let video = player
-            .video_streams
+        match error {
+            Some(error) => Err(error),
+            None => Err(ResolverError::MissingRequiredData(
+                "Failed to resolve an Api Track",
+            )),
+        }
+    }
+
+    /**
+     * Follows the HLS playlist of a livestream with the headers of the client that resolved it. The PO token of its stream urls is added to the manifest path
+     */
+    async fn open_live_stream(
+        &self,
+        player: &VideoPlayer,
+        manifest_url: &str,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, ResolverError> {
+        let po_token = player
+            .audio_streams
             .iter()
-            .filter(|stream| self.video_itags.contains(&stream.itag))
-            .reduce(|prev, current| {
-                if prev.bitrate > current.bitrate {
-                    prev
-                } else {
-                    current
-                }
-            });
+            .map(|stream| stream.url.as_str())
+            .chain(
+                player
+                    .video_streams
+                    .iter()
+                    .map(|stream| stream.url.as_str()),
+            )
+            .find_map(|url| query_param(url, "pot"));
+
+        let manifest_url = match po_token {
+            Some(po_token) => format!("{}/pot/{po_token}", manifest_url.trim_end_matches('/')),
+            None => manifest_url.to_string(),
+        };
+
+        let mut headers = HeaderMap::new();
 
-        if let Some(stream) = audio
-            && audio.is_none()
+        if let Ok(user_agent) =
+            HeaderValue::from_str(&self.rusty_pipe.query().user_agent(player.client_type))
         {
+            headers.insert(USER_AGENT, user_agent);
+        }
+
+        let stream = HlsStream::open(self.get_client(), &manifest_url, headers).await?;
+
+        Ok(stream.into_audio_stream())
+    }
+
+    /**
+     * Picks the best audio stream, opus first since songbird can pass it through without transcoding. Video streams are only used when no audio stream matches
+     */
+    fn pick_stream(&self, player: &VideoPlayer) -> Option<(String, Option<u64>)> {
+        let audio = player
+            .audio_streams
+            .iter()
+            .filter(|stream| self.audio_itags.contains(&stream.itag))
+            .max_by_key(|stream| (stream.codec == AudioCodec::Opus, stream.bitrate));
+
+        if let Some(stream) = audio {
             tracing::info!(
                 "Picked [{}] [{} ({}/s)] for the playback",
                 stream.itag,
                 stream.mime,
                 ByteSize::b(stream.bitrate as u64).display().iec_short()
             );
-        }
 
-        let mut url = audio.map(|stream| &stream.url);
-
-        if url.is_none() {
-            url = video.map(|stream| &stream.url);
+            return Some((stream.url.to_owned(), Some(stream.size)));
         }
 
-        let mut request = HttpRequest::new(
-            self.get_client(),
-            url.ok_or(ResolverError::MissingRequiredData("Stream to Play"))?
-                .clone(),
-        );
-
-        let stream = request.create_async().await?;
-
-        let seekable = SeekableSource::new(stream.input);
+        let video = player
+            .video_streams
+            .iter()
+            .filter(|stream| self.video_itags.contains(&stream.itag))
+            .max_by_key(|stream| stream.bitrate)?;
 
-        let input = Input::Live(
-            LiveInput::Raw(seekable.into_audio_stream(stream.hint)),
-            None,
+        tracing::info!(
+            "Picked [{}] [{} ({}/s)] for the playback",
+            video.itag,
+            video.mime,
+            ByteSize::b(video.bitrate as u64).display().iec_short()
         );
 
-        Ok(Track::new_with_data(input, Arc::new(track)))
+        Some((video.url.to_owned(), video.size))
     }
-}
 
-impl Youtube {
     pub fn readable_client_type(&self, client: &ClientType) -> &'static str {
         match client {
             ClientType::Desktop => "Desktop",
@@ -446,3 +1400,151 @@ impl Youtube {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_rejected_stream_statuses() {
+        let rejected = |message: &str| AudioStreamError::Fail(message.into());
+
+        assert_eq!(
+            stream_status(&rejected("failed with http status code: 403 Forbidden")),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            stream_status(&rejected("failed with http status code: 410 Gone")),
+            Some(StatusCode::GONE)
+        );
+        assert_eq!(
+            stream_status(&rejected("connection reset while reading itag 403")),
+            None
+        );
+        assert_eq!(stream_status(&AudioStreamError::Unsupported), None);
+    }
+
+    #[tokio::test]
+    async fn reads_statuses_from_songbird_requests() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/videoplayback", listener.local_addr().unwrap());
+
+        let router = axum::Router::new().route(
+            "/videoplayback",
+            axum::routing::get(|| async { StatusCode::GONE }),
+        );
+
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let client = Client::builder().no_proxy().build().unwrap();
+
+        let Err(error) = HttpRequest::new(client, url).create_async().await else {
+            panic!("Expected the request to be rejected");
+        };
+
+        assert_eq!(stream_status(&error), Some(StatusCode::GONE));
+    }
+
+    #[test]
+    fn encodes_varints() {
+        assert_eq!(varint(1), [0x01]);
+        assert_eq!(varint(300), [0xac, 0x02]);
+        assert_eq!(proto_varint(1, 100), [0x08, 0x64]);
+    }
+
+    #[test]
+    fn builds_offset_continuations() {
+        let token = playlist_continuation("PLabc", 200);
+
+        let outer = BASE64_URL_SAFE.decode(token.replace("%3D", "=")).unwrap();
+
+        assert!(outer.starts_with(&varint((80226972 << 3) | 2)));
+        assert!(String::from_utf8_lossy(&outer).contains("VLPLabc"));
+        assert!(outer.ends_with(&proto_bytes(35, b"PLabc")));
+        assert_ne!(token, playlist_continuation("PLabc", 300));
+    }
+
+    fn page(ids: &[&str], ctoken: &str) -> Paginator<VideoItem> {
+        let mut page = Paginator::default();
+
+        page.items = ids
+            .iter()
+            .map(|id| {
+                serde_json::from_value(serde_json::json!({
+                    "id": id,
+                    "name": id,
+                    "duration": 60,
+                    "thumbnail": [],
+                    "channel": null,
+                    "publish_date": null,
+                    "publish_date_txt": null,
+                    "view_count": null,
+                    "is_live": false,
+                    "is_short": false,
+                    "is_upcoming": false,
+                    "short_description": null,
+                }))
+                .unwrap()
+            })
+            .collect();
+        page.ctoken = Some(ctoken.to_string());
+
+        page
+    }
+
+    async fn append(
+        pages: Vec<Result<Paginator<VideoItem>, rustypipe::error::Error>>,
+    ) -> (Vec<String>, Option<String>) {
+        let mut videos = page(&["a", "b"], "first");
+
+        append_offset_pages("PLabc", &mut videos, stream::iter(pages)).await;
+
+        (
+            videos.items.into_iter().map(|video| video.id).collect(),
+            videos.ctoken,
+        )
+    }
+
+    #[tokio::test]
+    async fn appends_built_pages_in_order() {
+        let (ids, ctoken) = append(vec![
+            Ok(page(&["c", "d"], "second")),
+            Ok(page(&["e"], "third")),
+        ])
+        .await;
+
+        assert_eq!(ids, ["a", "b", "c", "d", "e"]);
+        assert_eq!(ctoken.as_deref(), Some("third"));
+    }
+
+    // whatever YouTube answers to a token it no longer understands, loading carries on from the last good page
+    #[tokio::test]
+    async fn falls_back_when_built_pages_go_wrong() {
+        let broken = [
+            Ok(page(&[], "empty")),
+            Ok(page(&["a", "b"], "first again")),
+            Err(rustypipe::error::Error::HttpStatus(
+                400,
+                "Bad Request".into(),
+            )),
+        ];
+
+        for broken in broken {
+            let (ids, ctoken) = append(vec![
+                Ok(page(&["c", "d"], "second")),
+                broken,
+                Ok(page(&["e"], "third")),
+            ])
+            .await;
+
+            assert_eq!(ids, ["a", "b", "c", "d"]);
+            assert_eq!(ctoken.as_deref(), Some("second"));
+        }
+    }
+
+    #[test]
+    fn unknown_durations_are_streams() {
+        assert_eq!(track_length(Some(212)), 212_000);
+        assert_eq!(track_length(None), u64::MAX);
+    }
+}
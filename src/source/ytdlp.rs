@@ -0,0 +1,700 @@
+use crate::{
+    Config,
+    models::{
+        ApiPlaylistInfo, ApiTrack, ApiTrackInfo, ApiTrackPlaylist, ApiTrackResult, PluginInfo,
+    },
+    util::{
+        config::YtdlpConfig,
+        encoder::encode_base64,
+        errors::ResolverError,
+        seek::SeekableSource,
+        source::{Query, Source},
+        url::{URL_REGEX, parse_http_url},
+    },
+};
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::{
+    Client,
+    header::{HeaderMap, HeaderName, HeaderValue},
+};
+use serde_json::Value;
+use songbird::{
+    input::{ChildContainer, Compose, HttpRequest, Input, LiveInput},
+    tracks::Track,
+};
+use std::{
+    process::{Command as StdCommand, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{process::Command, time::timeout};
+
+const SEARCH_PREFIXES: [&str; 1] = ["ytdlsearch:"];
+const AUDIO_FORMAT: &str = "bestaudio/best";
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/**
+ * Fallback source that shells out to a yt-dlp binary, for when RustyPipe breaks or a site has no source of its own
+ */
+pub struct Ytdlp {
+    client: Client,
+    path: String,
+    args: Vec<String>,
+    search_limit: usize,
+    url_patterns: Vec<Regex>,
+}
+
+#[async_trait]
+impl Source for Ytdlp {
+    fn get_name(&self) -> &'static str {
+        "ytdlp"
+    }
+
+    fn get_client(&self) -> Client {
+        self.client.clone()
+    }
+
+    fn search_prefixes(&self) -> &[&'static str] {
+        &SEARCH_PREFIXES
+    }
+
+    fn url_patterns(&self) -> &[Regex] {
+        &self.url_patterns
+    }
+
+    fn priority(&self) -> u8 {
+        2
+    }
+
+    // the query ends up as a yt-dlp argument, so only a whole http(s) url is accepted
+    fn parse_query(&self, query: &str) -> Option<Query> {
+        if SEARCH_PREFIXES
+            .iter()
+            .any(|prefix| query.starts_with(prefix))
+        {
+            return Some(Query::Search(query.to_string()));
+        }
+
+        parse_http_url(query).map(|url| Query::Url(url.to_string()))
+    }
+
+    async fn resolve(&self, query: Query) -> Result<ApiTrackResult, ResolverError> {
+        match query {
+            Query::Search(query) => {
+                let term = SEARCH_PREFIXES
+                    .iter()
+                    .find_map(|prefix| query.strip_prefix(prefix))
+                    .unwrap_or(&query);
+
+                let value = self
+                    .metadata(&format!("ytsearch{}:{term}", self.search_limit), true)
+                    .await?;
+
+                let tracks = self.make_tracks(&value)?;
+
+                if tracks.is_empty() {
+                    return Ok(ApiTrackResult::Empty(None));
+                }
+
+                Ok(ApiTrackResult::Search(tracks))
+            }
+            Query::Url(url) => {
+                let value = self.metadata(&url, true).await?;
+
+                if value["_type"].as_str() != Some("playlist") {
+                    return Ok(self
+                        .make_track(&value)?
+                        .map(ApiTrackResult::Track)
+                        .unwrap_or(ApiTrackResult::Empty(None)));
+                }
+
+                let tracks = self.make_tracks(&value)?;
+
+                if tracks.is_empty() {
+                    return Ok(ApiTrackResult::Empty(None));
+                }
+
+                let mut plugin_info = PluginInfo::new();
+
+                plugin_info.insert(String::from("url"), Value::from(url));
+                plugin_info.insert(String::from("extractor"), value["extractor_key"].clone());
+
+                Ok(ApiTrackResult::Playlist(ApiTrackPlaylist {
+                    info: ApiPlaylistInfo {
+                        name: value["title"]
+                            .as_str()
+                            .unwrap_or("Unknown Playlist")
+                            .to_string(),
+                        selected_track: -1,
+                    },
+                    plugin_info,
+                    tracks,
+                }))
+            }
+        }
+    }
+
+    async fn make_playable(&self, track: ApiTrack) -> Result<Track, ResolverError> {
+        // encoded tracks come from clients, so the uri is checked again before handing it to yt-dlp
+        let uri = track
+            .info
+            .uri
+            .as_deref()
+            .ok_or(ResolverError::MissingRequiredData("uri"))
+            .and_then(|uri| parse_http_url(uri).ok_or(ResolverError::InputNotSupported))?
+            .to_string();
+
+        let value = self.metadata(&uri, false).await?;
+
+        let url = value["url"].as_str();
+        let protocol = value["protocol"].as_str().unwrap_or_default();
+
+        // plain http formats are streamed directly, anything else (hls, dash) is piped through yt-dlp
+        let input = match url {
+            Some(url) if protocol == "http" || protocol == "https" => {
+                let mut headers = HeaderMap::new();
+
+                for (name, value) in value["http_headers"].as_object().into_iter().flatten() {
+                    let (Ok(name), Some(Ok(value))) = (
+                        HeaderName::from_bytes(name.as_bytes()),
+                        value.as_str().map(HeaderValue::from_str),
+                    ) else {
+                        continue;
+                    };
+
+                    headers.insert(name, value);
+                }
+
+                let mut request =
+                    HttpRequest::new_with_headers(self.get_client(), url.to_string(), headers);
+
+                let stream = request.create_async().await?;
+
+                let seekable = SeekableSource::new(stream.input);
+
+                Input::Live(
+                    LiveInput::Raw(seekable.into_audio_stream(stream.hint)),
+                    None,
+                )
+            }
+            _ => {
+                tracing::debug!("Piping [{}] through yt-dlp [Protocol: {}]", uri, protocol);
+
+                let child = StdCommand::new(&self.path)
+                    .args(&self.args)
+                    .args([
+                        "--no-warnings",
+                        "--quiet",
+                        "-f",
+                        AUDIO_FORMAT,
+                        "-o",
+                        "-",
+                        "--",
+                    ])
+                    .arg(&uri)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(|error| ResolverError::Ytdlp(error.to_string()))?;
+
+                ChildContainer::from(child).into()
+            }
+        };
+
+        Ok(Track::new_with_data(input, Arc::new(track)))
+    }
+}
+
+impl Ytdlp {
+    pub fn new(client: Option<Client>) -> Self {
+        let config = Config.load();
+
+        Self::from_config(client, config.ytdlp_config.as_ref())
+    }
+
+    fn from_config(client: Option<Client>, ytdlp_config: Option<&YtdlpConfig>) -> Self {
+        Self {
+            client: client.unwrap_or_default(),
+            path: ytdlp_config
+                .and_then(|config| config.path.clone())
+                .unwrap_or(String::from("yt-dlp")),
+            args: ytdlp_config
+                .and_then(|config| config.args.clone())
+                .unwrap_or_default(),
+            search_limit: ytdlp_config
+                .and_then(|config| config.search_limit)
+                .unwrap_or(10),
+            url_patterns: vec![URL_REGEX.clone()],
+        }
+    }
+
+    /**
+     * Runs `yt-dlp -J`. Flat listing is used when loading, since playlist entries only need their urls until they are played
+     */
+    async fn metadata(&self, target: &str, flat: bool) -> Result<Value, ResolverError> {
+        let mut command = Command::new(&self.path);
+
+        command
+            .args(&self.args)
+            .args(["--no-warnings", "-J"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        if flat {
+            command.arg("--flat-playlist");
+        } else {
+            command.args(["-f", AUDIO_FORMAT]);
+        }
+
+        let output = timeout(COMMAND_TIMEOUT, command.args(["--", target]).output())
+            .await
+            .map_err(|_| ResolverError::Ytdlp(String::from("Timed out")))?
+            .map_err(|error| ResolverError::Ytdlp(error.to_string()))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+
+            return Err(ResolverError::Ytdlp(
+                stderr
+                    .lines()
+                    .rev()
+                    .find(|line| !line.trim().is_empty())
+                    .unwrap_or("Exited with an error")
+                    .to_string(),
+            ));
+        }
+
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    fn make_tracks(&self, value: &Value) -> Result<Vec<ApiTrack>, ResolverError> {
+        let mut tracks = Vec::new();
+
+        for entry in value["entries"].as_array().into_iter().flatten() {
+            if let Some(track) = self.make_track(entry)? {
+                tracks.push(track);
+            }
+        }
+
+        Ok(tracks)
+    }
+
+    /**
+     * Entries without a url can't be played later and are skipped
+     */
+    fn make_track(&self, value: &Value) -> Result<Option<ApiTrack>, ResolverError> {
+        let Some(uri) = ["webpage_url", "url"]
+            .iter()
+            .filter_map(|key| value[key].as_str())
+            .find(|url| url.starts_with("http"))
+        else {
+            return Ok(None);
+        };
+
+        let is_stream = value["is_live"].as_bool().unwrap_or(false)
+            || value["live_status"].as_str() == Some("is_live");
+
+        let duration = value["duration"]
+            .as_f64()
+            .filter(|_| !is_stream)
+            .map(|duration| (duration * 1000.0) as u64);
+
+        let author = ["artist", "uploader", "channel", "creator"]
+            .iter()
+            .find_map(|key| value[key].as_str())
+            .unwrap_or("Unknown");
+
+        let artwork_url = value["thumbnail"].as_str().or_else(|| {
+            value["thumbnails"]
+                .as_array()
+                .and_then(|thumbnails| thumbnails.last())
+                .and_then(|thumbnail| thumbnail["url"].as_str())
+        });
+
+        let info = ApiTrackInfo {
+            identifier: value["id"].as_str().unwrap_or(uri).to_string(),
+            is_seekable: duration.is_some(),
+            author: author.to_string(),
+            length: duration.unwrap_or(u64::MAX),
+            is_stream,
+            position: 0,
+            title: value["title"].as_str().unwrap_or("Unknown").to_string(),
+            uri: Some(uri.to_string()),
+            artwork_url: artwork_url.map(String::from),
+            isrc: None,
+            source_name: self.get_name().into(),
+        };
+
+        let mut plugin_info = PluginInfo::new();
+
+        plugin_info.insert(
+            String::from("extractor"),
+            value["extractor_key"]
+                .as_str()
+                .or(value["ie_key"].as_str())
+                .map(Value::from)
+                .unwrap_or(Value::Null),
+        );
+
+        Ok(Some(ApiTrack {
+            encoded: encode_base64(&info)?,
+            info,
+            plugin_info,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AvailableSources,
+        source::http::Http,
+        util::{decoder::decode_base64, source::find_sources},
+    };
+    use axum::{
+        Router,
+        http::{HeaderMap as RequestHeaders, header::USER_AGENT},
+        routing,
+    };
+    use std::{fs, io::Read, os::unix::fs::PermissionsExt, path::PathBuf, sync::LazyLock};
+    use tokio::net::TcpListener;
+
+    // answers like yt-dlp does for the handful of targets the tests use
+    const FAKE_YTDLP: &str = r#"#!/bin/sh
+media_url=""
+format=""
+output=""
+target=""
+
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --media-url) media_url="$2"; shift 2 ;;
+        -f) format="$2"; shift 2 ;;
+        -o) output="$2"; shift 2 ;;
+        --) target="$2"; shift 2 ;;
+        -*) shift ;;
+        *) echo "ERROR: [$1] was passed without a -- separator" >&2; exit 1 ;;
+    esac
+done
+
+if [ "$output" = "-" ]; then
+    printf 'piped-audio'
+    exit 0
+fi
+
+case "$target" in
+    ytsearch2:*)
+        cat <<JSON
+{"_type": "playlist", "id": "daft punk", "title": "daft punk", "extractor_key": "YoutubeSearch", "entries": [
+    {"_type": "url", "ie_key": "Youtube", "id": "FGBhQbmPwH8", "url": "https://www.youtube.com/watch?v=FGBhQbmPwH8", "title": "Daft Punk - One More Time", "duration": 320.0, "channel": "Daft Punk", "thumbnails": [{"url": "https://i.ytimg.com/vi/FGBhQbmPwH8/hqdefault.jpg"}]},
+    {"_type": "url", "ie_key": "Youtube", "id": "GDpmVUEjagg", "url": "https://www.youtube.com/watch?v=GDpmVUEjagg", "title": "Daft Punk - Harder, Better, Faster, Stronger", "duration": 224.0, "channel": "Daft Punk"}
+]}
+JSON
+        ;;
+    *fail*)
+        echo "WARNING: [generic] Falling back on generic information extractor" >&2
+        echo "ERROR: [generic] Unsupported URL: $target" >&2
+        echo "" >&2
+        exit 1
+        ;;
+    *playlist*)
+        cat <<JSON
+{"_type": "playlist", "id": "72157594298451617", "title": "Field Recordings", "extractor_key": "Bandcamp", "entries": [
+    {"_type": "url", "ie_key": "Bandcamp", "id": "rain", "url": "https://example.bandcamp.com/track/rain", "title": "Rain", "duration": 61.5, "uploader": "Example"},
+    {"_type": "url", "ie_key": "Bandcamp", "id": "private", "title": "Private Track"},
+    {"_type": "url", "ie_key": "Bandcamp", "id": "wind", "url": "https://example.bandcamp.com/track/wind", "title": "Wind", "duration": 93.0, "uploader": "Example"}
+]}
+JSON
+        ;;
+    *live*)
+        cat <<JSON
+{"id": "live", "title": "Radio", "webpage_url": "https://example.com/live", "extractor_key": "Generic", "is_live": true, "duration": 12.0, "url": "https://example.com/live/index.m3u8", "protocol": "m3u8_native"}
+JSON
+        ;;
+    *)
+        cat <<JSON
+{"id": "clip", "title": "A Clip", "webpage_url": "$target", "extractor_key": "Vimeo", "uploader": "Someone", "duration": 12.25, "thumbnail": "https://example.com/clip.jpg", "url": "$media_url", "protocol": "https", "format": "$format", "http_headers": {"User-Agent": "fake-yt-dlp"}}
+JSON
+        ;;
+esac
+"#;
+
+    static FAKE_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+        let directory = std::env::temp_dir().join(format!("ibuki-ytdlp-{}", std::process::id()));
+
+        fs::create_dir_all(&directory).unwrap();
+
+        let path = directory.join("yt-dlp");
+
+        fs::write(&path, FAKE_YTDLP).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        path
+    });
+
+    fn ytdlp(media_url: &str) -> Ytdlp {
+        let config = YtdlpConfig {
+            path: Some(FAKE_PATH.to_string_lossy().to_string()),
+            args: Some(vec![String::from("--media-url"), media_url.to_string()]),
+            search_limit: Some(2),
+        };
+
+        Ytdlp::from_config(
+            Some(Client::builder().no_proxy().build().unwrap()),
+            Some(&config),
+        )
+    }
+
+    async fn resolve(query: Query) -> Result<ApiTrackResult, ResolverError> {
+        ytdlp("").resolve(query).await
+    }
+
+    fn read_all(track: Track) -> (bool, Vec<u8>) {
+        let Input::Live(LiveInput::Raw(mut stream), _) = track.input else {
+            panic!("Expected a raw live input");
+        };
+
+        let mut bytes = Vec::new();
+
+        stream.input.read_to_end(&mut bytes).unwrap();
+
+        (stream.input.is_seekable(), bytes)
+    }
+
+    #[tokio::test]
+    async fn resolves_a_single_video() {
+        let result = resolve(Query::Url(String::from("https://vimeo.com/76979871")))
+            .await
+            .unwrap();
+
+        let ApiTrackResult::Track(track) = result else {
+            panic!("Expected a track, got {result:?}");
+        };
+
+        assert_eq!(track.info.identifier, "clip");
+        assert_eq!(track.info.title, "A Clip");
+        assert_eq!(track.info.author, "Someone");
+        assert_eq!(track.info.length, 12_250);
+        assert!(track.info.is_seekable);
+        assert!(!track.info.is_stream);
+        assert_eq!(
+            track.info.uri.as_deref(),
+            Some("https://vimeo.com/76979871")
+        );
+        assert_eq!(
+            track.info.artwork_url.as_deref(),
+            Some("https://example.com/clip.jpg")
+        );
+        assert_eq!(track.plugin_info["extractor"], "Vimeo");
+    }
+
+    #[tokio::test]
+    async fn resolves_live_streams_without_a_length() {
+        let result = resolve(Query::Url(String::from("https://example.com/live")))
+            .await
+            .unwrap();
+
+        let ApiTrackResult::Track(track) = result else {
+            panic!("Expected a track, got {result:?}");
+        };
+
+        assert!(track.info.is_stream);
+        assert!(!track.info.is_seekable);
+        assert_eq!(track.info.length, u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn resolves_flat_playlists() {
+        let url = "https://example.bandcamp.com/playlist/field-recordings";
+
+        let result = resolve(Query::Url(url.to_string())).await.unwrap();
+
+        let ApiTrackResult::Playlist(playlist) = result else {
+            panic!("Expected a playlist, got {result:?}");
+        };
+
+        assert_eq!(playlist.info.name, "Field Recordings");
+        assert_eq!(playlist.plugin_info["url"], url);
+        assert_eq!(playlist.plugin_info["extractor"], "Bandcamp");
+
+        // entries without a url can't be played later
+        assert_eq!(
+            playlist
+                .tracks
+                .iter()
+                .map(|track| track.info.identifier.as_str())
+                .collect::<Vec<_>>(),
+            ["rain", "wind"]
+        );
+        assert_eq!(playlist.tracks[0].info.author, "Example");
+        assert_eq!(playlist.tracks[0].plugin_info["extractor"], "Bandcamp");
+    }
+
+    #[tokio::test]
+    async fn searches_with_the_configured_limit() {
+        let result = resolve(Query::Search(String::from("ytdlsearch:daft punk")))
+            .await
+            .unwrap();
+
+        let ApiTrackResult::Search(tracks) = result else {
+            panic!("Expected search results, got {result:?}");
+        };
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].info.title, "Daft Punk - One More Time");
+        assert_eq!(tracks[0].info.author, "Daft Punk");
+        assert_eq!(
+            tracks[0].info.uri.as_deref(),
+            Some("https://www.youtube.com/watch?v=FGBhQbmPwH8")
+        );
+        assert_eq!(
+            tracks[0].info.artwork_url.as_deref(),
+            Some("https://i.ytimg.com/vi/FGBhQbmPwH8/hqdefault.jpg")
+        );
+        assert_eq!(tracks[1].info.artwork_url, None);
+    }
+
+    #[tokio::test]
+    async fn reports_the_last_stderr_line_on_failure() {
+        let result = resolve(Query::Url(String::from("https://example.com/fail"))).await;
+
+        let Err(ResolverError::Ytdlp(message)) = result else {
+            panic!("Expected a yt-dlp error, got {result:?}");
+        };
+
+        assert_eq!(
+            message,
+            "ERROR: [generic] Unsupported URL: https://example.com/fail"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn streams_http_formats_directly() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let media_url = format!("http://{}/clip.m4a", listener.local_addr().unwrap());
+
+        // the format headers from yt-dlp have to be sent along
+        let router = Router::new().route(
+            "/clip.m4a",
+            routing::get(|headers: RequestHeaders| async move {
+                match headers
+                    .get(USER_AGENT)
+                    .and_then(|agent| agent.to_str().ok())
+                {
+                    Some("fake-yt-dlp") => "http-audio",
+                    _ => "wrong-headers",
+                }
+            }),
+        );
+
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let ytdlp = ytdlp(&media_url);
+
+        let ApiTrackResult::Track(track) = ytdlp
+            .resolve(Query::Url(String::from("https://vimeo.com/76979871")))
+            .await
+            .unwrap()
+        else {
+            panic!("Expected a track");
+        };
+
+        let track = ytdlp.make_playable(track).await.unwrap();
+
+        assert_eq!(read_all(track), (true, b"http-audio".to_vec()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pipes_other_protocols_through_ytdlp() {
+        let ytdlp = ytdlp("");
+
+        let ApiTrackResult::Track(track) = ytdlp
+            .resolve(Query::Url(String::from("https://example.com/live")))
+            .await
+            .unwrap()
+        else {
+            panic!("Expected a track");
+        };
+
+        let track = ytdlp.make_playable(track).await.unwrap();
+
+        assert_eq!(read_all(track), (false, b"piped-audio".to_vec()));
+    }
+
+    #[test]
+    fn only_accepts_whole_http_urls() {
+        let ytdlp = ytdlp("");
+
+        for query in [
+            "--exec=touch /tmp/pwned http://a.com",
+            "-o /tmp/pwned https://example.com/video",
+            "file:///etc/passwd",
+            "not a url",
+        ] {
+            assert!(ytdlp.parse_query(query).is_none(), "{query} was accepted");
+        }
+
+        assert!(matches!(
+            ytdlp.parse_query("https://vimeo.com/76979871"),
+            Some(Query::Url(url)) if url == "https://vimeo.com/76979871"
+        ));
+        assert!(matches!(
+            ytdlp.parse_query("ytdlsearch:--exec=true"),
+            Some(Query::Search(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_encoded_tracks_with_crafted_uris() {
+        let ytdlp = ytdlp("");
+
+        let ApiTrackResult::Track(track) = ytdlp
+            .resolve(Query::Url(String::from("https://vimeo.com/76979871")))
+            .await
+            .unwrap()
+        else {
+            panic!("Expected a track");
+        };
+
+        for uri in ["--exec=touch /tmp/pwned", "file:///etc/passwd"] {
+            let mut info = track.info.clone();
+
+            info.uri = Some(uri.to_string());
+
+            // goes through the same round trip as a track sent by a client
+            let encoded = encode_base64(&info).unwrap();
+            let info = decode_base64(&encoded).unwrap();
+
+            let result = ytdlp
+                .make_playable(ApiTrack {
+                    encoded,
+                    info,
+                    plugin_info: PluginInfo::new(),
+                })
+                .await;
+
+            assert!(
+                matches!(result, Err(ResolverError::InputNotSupported)),
+                "{uri} was played"
+            );
+        }
+    }
+
+    #[test]
+    fn http_runs_before_ytdlp() {
+        AvailableSources.insert(String::from("ytdlp"), Arc::new(ytdlp("")));
+        AvailableSources.insert(String::from("http"), Arc::new(Http::new(None)));
+
+        let names = find_sources("https://example.com/song.mp3")
+            .iter()
+            .map(|(source, _)| source.get_name())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["http", "ytdlp"]);
+    }
+}
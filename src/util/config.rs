@@ -1,36 +1,140 @@
+use super::errors::ConfigError;
+use super::tenant::DEFAULT_TENANT;
+use crate::source::deezer::model::DeezerQuality;
+use crate::source::youtube::YoutubeClient;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use serde_json::{Map, Value};
+use std::{env, fs, path::Path};
 
-#[derive(Debug, Serialize, Deserialize)]
+static DEFAULT_PATHS: [&str; 4] = [
+    "./config.json",
+    "./config.yaml",
+    "./config.yml",
+    "./config.toml",
+];
+static ENV_PREFIX: &str = "IBUKI_";
+static ENV_SEPARATOR: &str = "__";
+// these are read on their own and are not part of the config structure
+static ENV_RESERVED: [&str; 3] = ["IBUKI_CONFIG", "IBUKI_LOG_LEVEL", "IBUKI_LOG_FORMAT"];
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DeezerConfig {
     pub decrypt_key: String,
     pub arl: String,
+    pub qualities: Option<Vec<DeezerQuality>>,
+    pub playlist_track_limit: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct YoutubeConfig {
     pub use_po_token: Option<bool>,
     pub use_oauth: Option<bool>,
     pub cookies: Option<String>,
+    pub channel_page_limit: Option<usize>,
+    pub playlist_track_limit: Option<usize>,
+    pub clients: Option<Vec<YoutubeClient>>,
+    pub audio_itags: Option<Vec<u32>>,
+    pub video_itags: Option<Vec<u32>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YtdlpConfig {
+    pub path: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub search_limit: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HttpConfig {}
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Compact,
+    Json,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFileConfig {
+    pub directory: String,
+    pub prefix: Option<String>,
+    pub rotation: Option<LogRotation>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsConfig {
+    pub token: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoggingConfig {
+    pub level: Option<String>,
+    pub format: Option<LogFormat>,
+    pub filters: Option<String>,
+    pub file: Option<LogFileConfig>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitBucketConfig {
+    pub capacity: u32,
+    pub refill_per_sec: f64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitConfig {
+    pub load: Option<RateLimitBucketConfig>,
+    pub player: Option<RateLimitBucketConfig>,
+    pub decode: Option<RateLimitBucketConfig>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyConfig {
+    pub name: String,
+    pub key: String,
+    pub allowed_user_ids: Option<Vec<u64>>,
+    pub max_players: Option<u32>,
+    pub sources: Option<Vec<String>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     pub port: u16,
     pub address: String,
     pub authorization: String,
+    pub api_keys: Option<Vec<ApiKeyConfig>>,
     pub player_update_secs: Option<u8>,
     pub status_update_secs: Option<u8>,
     pub deezer_config: Option<DeezerConfig>,
     pub youtube_config: Option<YoutubeConfig>,
+    pub ytdlp_config: Option<YtdlpConfig>,
     pub http_config: Option<HttpConfig>,
+    pub search_order: Option<Vec<String>>,
+    pub rate_limit_config: Option<RateLimitConfig>,
+    pub logging_config: Option<LoggingConfig>,
+    pub metrics_config: Option<MetricsConfig>,
 }
 
 impl Default for Config {
@@ -40,8 +144,480 @@ impl Default for Config {
 }
 
 impl Config {
+    /**
+     * Loads the config or exits with a readable error, since nothing can run without it
+     */
     pub fn new() -> Self {
-        let config = fs::read_to_string("./config.json").expect("Missing ./config.json");
-        serde_json::from_str::<Config>(&config).unwrap()
+        Config::load().unwrap_or_else(|error| {
+            eprintln!("Failed to load the config. {error}");
+            std::process::exit(1);
+        })
+    }
+
+    /**
+     * Resolution order is `--config <path>`, then `IBUKI_CONFIG`, then the default paths beside the executable.
+     * Values from `IBUKI_` prefixed environment variables are applied on top of the file
+     */
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut value = match Config::find_path()? {
+            Some(path) => Config::read(&path)?,
+            None => Value::Object(Map::new()),
+        };
+
+        let mut overrides = apply_env_overrides(&mut value, env::vars());
+
+        loop {
+            let error = match serde_path_to_error::deserialize::<_, Config>(value.clone()) {
+                Ok(config) => {
+                    config.validate()?;
+                    return Ok(config);
+                }
+                Err(error) => error,
+            };
+
+            let path = error.path().to_string();
+
+            // an env value that looked like json (e.g. a numeric password) but the field wants a string
+            let Some(index) = overrides
+                .iter()
+                .position(|(segments, _)| display_path(segments) == path)
+            else {
+                return Err(ConfigError::InvalidValue(path, error.inner().to_string()));
+            };
+
+            let (segments, raw) = overrides.swap_remove(index);
+
+            set_path(&mut value, &segments, Value::String(raw));
+        }
+    }
+
+    fn find_path() -> Result<Option<String>, ConfigError> {
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                return args
+                    .next()
+                    .map(Some)
+                    .ok_or(ConfigError::MissingArgument("--config"));
+            }
+
+            if let Some(path) = arg.strip_prefix("--config=") {
+                return Ok(Some(path.to_string()));
+            }
+        }
+
+        if let Ok(path) = env::var("IBUKI_CONFIG") {
+            return Ok(Some(path));
+        }
+
+        Ok(DEFAULT_PATHS
+            .iter()
+            .find(|path| Path::new(path).exists())
+            .map(|path| path.to_string()))
+    }
+
+    fn read(path: &str) -> Result<Value, ConfigError> {
+        if !Path::new(path).exists() {
+            return Err(ConfigError::NotFound(path.to_string()));
+        }
+
+        let content =
+            fs::read_to_string(path).map_err(|error| ConfigError::Io(path.to_string(), error))?;
+
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let parsed = match extension.as_str() {
+            "json" => serde_json::from_str::<Value>(&content).map_err(|error| error.to_string()),
+            "yaml" | "yml" => {
+                serde_yaml::from_str::<Value>(&content).map_err(|error| error.to_string())
+            }
+            "toml" => toml::from_str::<Value>(&content).map_err(|error| error.to_string()),
+            _ => return Err(ConfigError::UnsupportedFormat(path.to_string())),
+        };
+
+        parsed.map_err(|error| ConfigError::Parse(path.to_string(), error))
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        if self.address.trim().is_empty() {
+            errors.push(String::from("address must not be empty"));
+        }
+
+        if self.authorization.is_empty() {
+            errors.push(String::from("authorization must not be empty"));
+        }
+
+        if self
+            .metrics_config
+            .as_ref()
+            .is_some_and(|metrics_config| metrics_config.token.is_empty())
+        {
+            errors.push(String::from("metricsConfig.token must not be empty"));
+        }
+
+        if self.player_update_secs == Some(0) {
+            errors.push(String::from("playerUpdateSecs must be greater than 0"));
+        }
+
+        if self.status_update_secs == Some(0) {
+            errors.push(String::from("statusUpdateSecs must be greater than 0"));
+        }
+
+        if let Some(deezer_config) = self.deezer_config.as_ref() {
+            if deezer_config.decrypt_key.len() < 16 {
+                errors.push(String::from(
+                    "deezerConfig.decryptKey must be at least 16 characters long",
+                ));
+            }
+
+            if deezer_config.arl.is_empty() {
+                errors.push(String::from("deezerConfig.arl must not be empty"));
+            }
+
+            if deezer_config
+                .qualities
+                .as_ref()
+                .is_some_and(|qualities| qualities.is_empty())
+            {
+                errors.push(String::from("deezerConfig.qualities must not be empty"));
+            }
+
+            if deezer_config.playlist_track_limit == Some(0) {
+                errors.push(String::from(
+                    "deezerConfig.playlistTrackLimit must be greater than 0",
+                ));
+            }
+        }
+
+        if self
+            .youtube_config
+            .as_ref()
+            .is_some_and(|youtube_config| youtube_config.channel_page_limit == Some(0))
+        {
+            errors.push(String::from(
+                "youtubeConfig.channelPageLimit must be greater than 0",
+            ));
+        }
+
+        if self
+            .youtube_config
+            .as_ref()
+            .is_some_and(|youtube_config| youtube_config.playlist_track_limit == Some(0))
+        {
+            errors.push(String::from(
+                "youtubeConfig.playlistTrackLimit must be greater than 0",
+            ));
+        }
+
+        if self
+            .youtube_config
+            .as_ref()
+            .and_then(|youtube_config| youtube_config.clients.as_ref())
+            .is_some_and(|clients| clients.is_empty())
+        {
+            errors.push(String::from("youtubeConfig.clients must not be empty"));
+        }
+
+        if let Some(ytdlp_config) = self.ytdlp_config.as_ref() {
+            if ytdlp_config
+                .path
+                .as_ref()
+                .is_some_and(|path| path.trim().is_empty())
+            {
+                errors.push(String::from("ytdlpConfig.path must not be empty"));
+            }
+
+            if ytdlp_config.search_limit == Some(0) {
+                errors.push(String::from(
+                    "ytdlpConfig.searchLimit must be greater than 0",
+                ));
+            }
+        }
+
+        for (index, name) in self.search_order.iter().flatten().enumerate() {
+            if name.trim().is_empty() {
+                errors.push(format!("searchOrder[{index}] must not be empty"));
+            }
+        }
+
+        for (index, api_key) in self.api_keys.iter().flatten().enumerate() {
+            if api_key.name.is_empty() {
+                errors.push(format!("apiKeys[{index}].name must not be empty"));
+            }
+
+            // sessions and player limits are tied to the tenant name
+            if api_key.name == DEFAULT_TENANT {
+                errors.push(format!(
+                    "apiKeys[{index}].name must not be {DEFAULT_TENANT}, it is reserved for the authorization key"
+                ));
+            }
+
+            if self
+                .api_keys
+                .iter()
+                .flatten()
+                .take(index)
+                .any(|other| other.name == api_key.name)
+            {
+                errors.push(format!("apiKeys[{index}].name must be unique"));
+            }
+
+            if api_key.key.is_empty() {
+                errors.push(format!("apiKeys[{index}].key must not be empty"));
+            }
+        }
+
+        if let Some(rate_limit_config) = self.rate_limit_config.as_ref() {
+            let buckets = [
+                ("load", &rate_limit_config.load),
+                ("player", &rate_limit_config.player),
+                ("decode", &rate_limit_config.decode),
+            ];
+
+            for (name, bucket) in buckets {
+                let Some(bucket) = bucket else {
+                    continue;
+                };
+
+                if bucket.capacity == 0 {
+                    errors.push(format!(
+                        "rateLimitConfig.{name}.capacity must be greater than 0"
+                    ));
+                }
+
+                if bucket.refill_per_sec <= 0.0 {
+                    errors.push(format!(
+                        "rateLimitConfig.{name}.refillPerSec must be greater than 0"
+                    ));
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(ConfigError::Validation(errors));
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Converts `DEEZER_CONFIG` into `deezerConfig` to match the serialized field names
+ */
+fn to_camel_case(segment: &str) -> String {
+    let mut result = String::new();
+    let mut upper = false;
+
+    for char in segment.chars() {
+        if char == '_' {
+            upper = !result.is_empty();
+            continue;
+        }
+
+        if upper {
+            result.extend(char.to_uppercase());
+            upper = false;
+        } else {
+            result.extend(char.to_lowercase());
+        }
+    }
+
+    result
+}
+
+/**
+ * Formats the segments the same way serde_path_to_error does, e.g. `apiKeys[0].key`
+ */
+fn display_path(segments: &[String]) -> String {
+    let mut path = String::new();
+
+    for segment in segments {
+        if segment.parse::<usize>().is_ok() {
+            path.push_str(&format!("[{segment}]"));
+        } else {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+    }
+
+    path
+}
+
+/**
+ * Applies `IBUKI_` variables into the config, nested fields are separated by `__`.
+ * Example: `IBUKI_DEEZER_CONFIG__ARL` sets `deezerConfig.arl` and `IBUKI_API_KEYS__0__KEY` sets `apiKeys[0].key`.
+ * Returns the overrides that were parsed as json, in case the field turns out to want the raw string
+ */
+fn apply_env_overrides(
+    value: &mut Value,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Vec<(Vec<String>, String)> {
+    let mut parsed = Vec::new();
+
+    for (name, raw) in vars {
+        if ENV_RESERVED.contains(&name.as_str()) {
+            continue;
+        }
+
+        let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        let segments = path
+            .split(ENV_SEPARATOR)
+            .map(to_camel_case)
+            .collect::<Vec<String>>();
+
+        if segments.iter().any(|segment| segment.is_empty()) {
+            continue;
+        }
+
+        let existing = get_path(value, &segments);
+
+        // strings stay as strings, anything else is parsed as json, e.g. numbers, booleans or arrays
+        let new_value = match existing {
+            Some(Value::String(_)) => Value::String(raw),
+            _ => match serde_json::from_str::<Value>(&raw) {
+                Ok(Value::String(string)) => Value::String(string),
+                Ok(json) => {
+                    parsed.push((segments.clone(), raw));
+                    json
+                }
+                Err(_) => Value::String(raw),
+            },
+        };
+
+        set_path(value, &segments, new_value);
+    }
+
+    parsed
+}
+
+fn get_path<'a>(value: &'a Value, segments: &[String]) -> Option<&'a Value> {
+    segments
+        .iter()
+        .try_fold(value, |current, segment| match current {
+            Value::Array(array) => array.get(segment.parse::<usize>().ok()?),
+            Value::Object(map) => map.get(segment),
+            _ => None,
+        })
+}
+
+fn set_path(current: &mut Value, segments: &[String], new_value: Value) {
+    let Some((segment, rest)) = segments.split_first() else {
+        *current = new_value;
+        return;
+    };
+
+    let index = segment.parse::<usize>().ok();
+
+    // missing parents are created as arrays for numeric segments, e.g. `apiKeys` in `IBUKI_API_KEYS__0__KEY`
+    if !current.is_object() && !current.is_array() {
+        *current = match index {
+            Some(_) => Value::Array(Vec::new()),
+            None => Value::Object(Map::new()),
+        };
+    }
+
+    match current {
+        Value::Array(array) => {
+            let Some(index) = index else {
+                return;
+            };
+
+            if array.len() <= index {
+                array.resize(index + 1, Value::Null);
+            }
+
+            set_path(&mut array[index], rest, new_value);
+        }
+        Value::Object(map) => set_path(
+            map.entry(segment.clone()).or_insert(Value::Null),
+            rest,
+            new_value,
+        ),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn numeric_segments_create_arrays() {
+        let mut value = json!({});
+
+        apply_env_overrides(
+            &mut value,
+            vars(&[
+                ("IBUKI_API_KEYS__0__KEY", "first"),
+                ("IBUKI_API_KEYS__0__MAX_PLAYERS", "50"),
+                ("IBUKI_API_KEYS__2__KEY", "third"),
+            ]),
+        );
+
+        assert_eq!(
+            value["apiKeys"],
+            json!([{ "key": "first", "maxPlayers": 50 }, null, { "key": "third" }])
+        );
+    }
+
+    #[test]
+    fn numeric_segments_extend_existing_arrays() {
+        let mut value = json!({ "apiKeys": [{ "key": "first", "name": "a" }] });
+
+        apply_env_overrides(
+            &mut value,
+            vars(&[
+                ("IBUKI_API_KEYS__0__MAX_PLAYERS", "50"),
+                ("IBUKI_API_KEYS__1__KEY", "second"),
+            ]),
+        );
+
+        assert_eq!(
+            value["apiKeys"],
+            json!([{ "key": "first", "name": "a", "maxPlayers": 50 }, { "key": "second" }])
+        );
+    }
+
+    #[test]
+    fn readme_example_deserializes() {
+        let mut value =
+            json!({ "port": 2333, "address": "0.0.0.0", "authorization": "youshallnotpass" });
+
+        apply_env_overrides(
+            &mut value,
+            vars(&[
+                ("IBUKI_PORT", "8080"),
+                ("IBUKI_API_KEYS__0__NAME", "bot"),
+                ("IBUKI_API_KEYS__0__KEY", "secret"),
+                ("IBUKI_API_KEYS__0__MAX_PLAYERS", "50"),
+            ]),
+        );
+
+        let config = serde_json::from_value::<Config>(value).unwrap();
+        let keys = config.api_keys.unwrap();
+
+        assert_eq!(config.port, 8080);
+        assert_eq!(keys[0].key, "secret");
+        assert_eq!(keys[0].max_players, Some(50));
     }
 }
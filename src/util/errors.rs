@@ -1,5 +1,5 @@
 use axum::body::Body;
-use axum::http::{self, StatusCode};
+use axum::http::{self, StatusCode, header::RETRY_AFTER};
 use axum::response::{IntoResponse, Response};
 use thiserror::Error;
 
@@ -9,6 +9,24 @@ pub enum ConverterError {
     NonZeroU64(u64),
 }
 
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Missing value for the {0} argument")]
+    MissingArgument(&'static str),
+    #[error("Config file [{0}] was not found")]
+    NotFound(String),
+    #[error("Failed to read config file [{0}] due to: {1}")]
+    Io(String, std::io::Error),
+    #[error("Config file [{0}] has an unsupported extension, use .json, .yaml, .yml or .toml")]
+    UnsupportedFormat(String),
+    #[error("Failed to parse config file [{0}] due to: {1}")]
+    Parse(String, String),
+    #[error("Invalid value at [{0}] due to: {1}")]
+    InvalidValue(String, String),
+    #[error("Config failed validation:\n  - {}", .0.join("\n  - "))]
+    Validation(Vec<String>),
+}
+
 #[derive(Error, Debug)]
 pub enum SeekableInitError {
     #[error("Request was not sent due to [{0}]")]
@@ -43,6 +61,32 @@ pub enum ResolverError {
     ToStr(#[from] reqwest::header::ToStrError),
     #[error("The track provided is not supported")]
     InputNotSupported,
+    #[error("Source [{0}] is disabled")]
+    SourceDisabled(String),
+    #[error("Source [{0}] rejected the configured credentials")]
+    InvalidCredentials(&'static str),
+    #[error("yt-dlp failed: {0}")]
+    Ytdlp(String),
+    #[error(transparent)]
+    Plugin(#[from] PluginError),
+}
+
+#[derive(Error, Debug)]
+pub enum PluginError {
+    #[error(transparent)]
+    Library(#[from] libloading::Error),
+    #[error("Plugin was built for abi version {0}, expected {1}")]
+    AbiMismatch(u32, u32),
+    #[error("Plugin returned invalid json: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("Plugin payload contains a nul byte")]
+    Nul(#[from] std::ffi::NulError),
+    #[error("Plugin returned a null pointer")]
+    NullResponse,
+    #[error("Plugin [{0}] failed: {1}")]
+    Call(String, String),
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
 }
 
 #[derive(Error, Debug)]
@@ -57,6 +101,8 @@ pub enum PlayerManagerError {
     MissingPlayer,
     #[error("A connection is required to execute this action")]
     MissingConnection,
+    #[error("Tenant {0} reached its limit of {1} player(s)")]
+    PlayerLimitReached(String, u32),
 }
 
 #[derive(Error, Debug)]
@@ -73,6 +119,8 @@ pub enum PlayerError {
     Resolver(#[from] ResolverError),
     #[error(transparent)]
     Control(#[from] songbird::error::ControlError),
+    #[error("Source {0} is not allowed for this tenant")]
+    SourceNotAllowed(String),
 }
 
 #[derive(Error, Debug)]
@@ -97,6 +145,8 @@ pub enum Base64EncodeError {
 pub enum EndpointError {
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Too many requests, retry again after [{0}s]")]
+    TooManyRequests(u64),
     #[error("Not found")]
     NotFound,
     #[error("Required option {0} missing in headers")]
@@ -121,6 +171,10 @@ pub enum EndpointError {
     PlayerManager(#[from] PlayerManagerError),
     #[error(transparent)]
     PlayerError(#[from] PlayerError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Plugin(#[from] PluginError),
 }
 
 impl IntoResponse for EndpointError {
@@ -131,6 +185,15 @@ impl IntoResponse for EndpointError {
             self
         );
 
+        if let EndpointError::TooManyRequests(retry_after) = self {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after.to_string())],
+                self.to_string(),
+            )
+                .into_response();
+        }
+
         let tuple = match self {
             EndpointError::MissingOption(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             EndpointError::UnprocessableEntity(_) => {
@@ -163,6 +226,10 @@ impl IntoResponse for EndpointError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 converter_error.to_string(),
             ),
+            EndpointError::PlayerManager(PlayerManagerError::PlayerLimitReached(..))
+            | EndpointError::PlayerError(PlayerError::SourceNotAllowed(_)) => {
+                (StatusCode::FORBIDDEN, self.to_string())
+            }
             EndpointError::PlayerManager(player_manager_error) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 player_manager_error.to_string(),
@@ -171,6 +238,13 @@ impl IntoResponse for EndpointError {
                 (StatusCode::INTERNAL_SERVER_ERROR, player_error.to_string())
             }
             EndpointError::Unauthorized => (StatusCode::FORBIDDEN, self.to_string()),
+            EndpointError::Config(config_error) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, config_error.to_string())
+            }
+            EndpointError::Plugin(plugin_error) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, plugin_error.to_string())
+            }
+            EndpointError::TooManyRequests(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
         };
 
         tuple.into_response()
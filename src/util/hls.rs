@@ -0,0 +1,604 @@
+use crate::util::errors::ResolverError;
+use flume::{Receiver, Sender, bounded};
+use reqwest::{Client, Url, header::HeaderMap};
+use songbird::input::AudioStream;
+use std::{
+    cmp::min,
+    io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom},
+    time::Duration,
+};
+use symphonia::core::{io::MediaSource, probe::Hint};
+use tokio::{
+    task::{JoinHandle, block_in_place},
+    time::sleep,
+};
+
+// how many segments behind the live edge playback starts, like most players do
+const LIVE_EDGE_SEGMENTS: usize = 3;
+// segments buffered before the reader catches up, keeps playback near the live edge
+const BUFFERED_SEGMENTS: usize = 4;
+const MAX_FAILURES: u32 = 5;
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+const STREAM_TYPE_ADTS_AAC: u8 = 0x0F;
+
+struct Variant {
+    bandwidth: u64,
+    audio_only: bool,
+    uri: String,
+}
+
+struct Segment {
+    sequence: u64,
+    discontinuity: bool,
+    uri: String,
+}
+
+struct MediaPlaylist {
+    target_duration: Duration,
+    segments: Vec<Segment>,
+    ended: bool,
+}
+
+/**
+ * Live input that follows a HLS playlist. Segments are fetched in a background task and the aac audio is sent to the reader
+ */
+pub struct HlsStream {
+    receiver: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    offset: usize,
+    task: JoinHandle<()>,
+}
+
+impl HlsStream {
+    /**
+     * Resolves a master playlist to its smallest audio variant and starts following it. Headers are sent with every playlist and segment request
+     */
+    pub async fn open(
+        client: Client,
+        url: &str,
+        headers: HeaderMap,
+    ) -> Result<Self, ResolverError> {
+        let mut url = Url::parse(url)
+            .map_err(|_| ResolverError::MissingRequiredData("Invalid HLS manifest url"))?;
+
+        let body = fetch_text(&client, &url, &headers).await?;
+
+        if body.contains("#EXT-X-STREAM-INF") {
+            let variant = pick_variant(parse_master(&body))
+                .ok_or(ResolverError::MissingRequiredData("HLS variant"))?;
+
+            url = url
+                .join(&variant.uri)
+                .map_err(|_| ResolverError::MissingRequiredData("Invalid HLS variant url"))?;
+        }
+
+        // fail early when the media playlist can't be read
+        let playlist = parse_media(&fetch_text(&client, &url, &headers).await?);
+
+        if playlist.segments.is_empty() {
+            return Err(ResolverError::MissingRequiredData("HLS segments"));
+        }
+
+        let (sender, receiver) = bounded(BUFFERED_SEGMENTS);
+
+        let task = tokio::spawn(follow_playlist(client, url, headers, sender));
+
+        Ok(Self {
+            receiver,
+            chunk: Vec::new(),
+            offset: 0,
+            task,
+        })
+    }
+
+    pub fn into_audio_stream(self) -> AudioStream<Box<dyn MediaSource>> {
+        let mut hint = Hint::new();
+
+        hint.with_extension("aac");
+
+        AudioStream {
+            input: Box::new(self),
+            hint: Some(hint),
+        }
+    }
+}
+
+impl Read for HlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        while self.offset >= self.chunk.len() {
+            match block_in_place(|| self.receiver.recv()) {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.offset = 0;
+                }
+                // the playlist ended or the task gave up
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let bytes_read = min(buf.len(), self.chunk.len() - self.offset);
+
+        buf[..bytes_read].copy_from_slice(&self.chunk[self.offset..self.offset + bytes_read]);
+
+        self.offset += bytes_read;
+
+        Ok(bytes_read)
+    }
+}
+
+impl Seek for HlsStream {
+    fn seek(&mut self, _: SeekFrom) -> IoResult<u64> {
+        Err(IoError::new(
+            ErrorKind::Unsupported,
+            "Live streams can't be seeked",
+        ))
+    }
+}
+
+impl MediaSource for HlsStream {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Drop for HlsStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn follow_playlist(client: Client, url: Url, headers: HeaderMap, sender: Sender<Vec<u8>>) {
+    let mut next_sequence: Option<u64> = None;
+    let mut demuxer = TsDemuxer::default();
+    let mut failures = 0;
+
+    loop {
+        let playlist = match fetch_text(&client, &url, &headers).await {
+            Ok(body) => {
+                failures = 0;
+                parse_media(&body)
+            }
+            Err(error) => {
+                failures += 1;
+
+                if failures >= MAX_FAILURES {
+                    tracing::warn!("Stopped following the HLS playlist: {}", error);
+                    return;
+                }
+
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let (Some(first), Some(last)) = (playlist.segments.first(), playlist.segments.last())
+        else {
+            sleep(playlist.target_duration).await;
+            continue;
+        };
+
+        let start = match next_sequence {
+            Some(sequence) if sequence < first.sequence => {
+                tracing::warn!(
+                    "Fell behind the live edge, skipping {} segments",
+                    first.sequence - sequence
+                );
+
+                demuxer = TsDemuxer::default();
+
+                first.sequence
+            }
+            Some(sequence) => sequence,
+            None => last
+                .sequence
+                .saturating_sub(LIVE_EDGE_SEGMENTS as u64 - 1)
+                .max(first.sequence),
+        };
+
+        for segment in playlist
+            .segments
+            .iter()
+            .filter(|segment| segment.sequence >= start)
+        {
+            // the stream parameters can change after a discontinuity
+            if segment.discontinuity {
+                demuxer = TsDemuxer::default();
+            }
+
+            next_sequence = Some(segment.sequence + 1);
+
+            let Ok(segment_url) = url.join(&segment.uri) else {
+                continue;
+            };
+
+            let bytes = match fetch_bytes(&client, &segment_url, &headers).await {
+                Ok(bytes) => bytes,
+                Err(error) => {
+                    tracing::warn!("Skipped HLS segment [{}]: {}", segment.sequence, error);
+                    continue;
+                }
+            };
+
+            let audio = demuxer.demux(&bytes);
+
+            if !audio.is_empty() && sender.send_async(audio).await.is_err() {
+                // the reader is gone
+                return;
+            }
+        }
+
+        if playlist.ended {
+            return;
+        }
+
+        sleep((playlist.target_duration / 2).max(Duration::from_secs(1))).await;
+    }
+}
+
+async fn fetch(
+    client: &Client,
+    url: &Url,
+    headers: &HeaderMap,
+) -> Result<reqwest::Response, ResolverError> {
+    let response = client
+        .get(url.clone())
+        .headers(headers.clone())
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(ResolverError::FailedStatusCode(
+            response.status().to_string(),
+        ));
+    }
+
+    Ok(response)
+}
+
+async fn fetch_text(
+    client: &Client,
+    url: &Url,
+    headers: &HeaderMap,
+) -> Result<String, ResolverError> {
+    Ok(fetch(client, url, headers).await?.text().await?)
+}
+
+async fn fetch_bytes(
+    client: &Client,
+    url: &Url,
+    headers: &HeaderMap,
+) -> Result<Vec<u8>, ResolverError> {
+    Ok(fetch(client, url, headers).await?.bytes().await?.to_vec())
+}
+
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let start = attributes.find(&format!("{name}="))? + name.len() + 1;
+    let value = &attributes[start..];
+
+    match value.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next(),
+        None => value.split(',').next(),
+    }
+}
+
+fn parse_master(body: &str) -> Vec<Variant> {
+    let mut variants = Vec::new();
+    let mut lines = body.lines().map(str::trim);
+
+    while let Some(line) = lines.next() {
+        let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+            continue;
+        };
+
+        let Some(uri) = lines.find(|line| !line.is_empty() && !line.starts_with('#')) else {
+            break;
+        };
+
+        let audio_only = attribute(attributes, "CODECS")
+            .map(|codecs| {
+                codecs
+                    .split(',')
+                    .all(|codec| codec.trim().starts_with("mp4a"))
+            })
+            .unwrap_or(false);
+
+        variants.push(Variant {
+            bandwidth: attribute(attributes, "BANDWIDTH")
+                .and_then(|bandwidth| bandwidth.parse().ok())
+                .unwrap_or(u64::MAX),
+            audio_only,
+            uri: uri.to_string(),
+        });
+    }
+
+    variants
+}
+
+/**
+ * Audio only variants win, then the lowest bandwidth since video is thrown away anyway
+ */
+fn pick_variant(variants: Vec<Variant>) -> Option<Variant> {
+    variants
+        .into_iter()
+        .min_by_key(|variant| (!variant.audio_only, variant.bandwidth))
+}
+
+fn parse_media(body: &str) -> MediaPlaylist {
+    let mut target_duration = Duration::from_secs(5);
+    let mut sequence = 0;
+    let mut discontinuity = false;
+    let mut segments = Vec::new();
+    let mut ended = false;
+
+    for line in body.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            if let Ok(seconds) = value.parse::<f64>() {
+                target_duration = Duration::from_secs_f64(seconds.max(0.0));
+            }
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = value.parse().unwrap_or(0);
+        } else if line == "#EXT-X-DISCONTINUITY" {
+            discontinuity = true;
+        } else if line == "#EXT-X-ENDLIST" {
+            ended = true;
+        } else if !line.starts_with('#') {
+            segments.push(Segment {
+                sequence,
+                discontinuity,
+                uri: line.to_string(),
+            });
+
+            sequence += 1;
+            discontinuity = false;
+        }
+    }
+
+    MediaPlaylist {
+        target_duration,
+        segments,
+        ended,
+    }
+}
+
+/**
+ * Pulls the adts aac stream out of mpeg-ts segments since symphonia can't demux transport streams
+ */
+#[derive(Default)]
+struct TsDemuxer {
+    pmt_pid: Option<u16>,
+    audio_pid: Option<u16>,
+    pes: Vec<u8>,
+}
+
+impl TsDemuxer {
+    fn demux(&mut self, data: &[u8]) -> Vec<u8> {
+        // segments that aren't transport streams are passed as is
+        if data.first() != Some(&TS_SYNC_BYTE) {
+            return data.to_vec();
+        }
+
+        let mut output = Vec::new();
+
+        for packet in data.chunks_exact(TS_PACKET_SIZE) {
+            if packet[0] != TS_SYNC_BYTE {
+                continue;
+            }
+
+            let payload_start = packet[1] & 0x40 != 0;
+            let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+            let adaptation = (packet[3] >> 4) & 0x03;
+
+            let mut offset = 4;
+
+            if adaptation & 0x02 != 0 {
+                offset += 1 + usize::from(packet[4]);
+            }
+
+            if adaptation & 0x01 == 0 || offset >= TS_PACKET_SIZE {
+                continue;
+            }
+
+            let payload = &packet[offset..];
+
+            if pid == 0 && payload_start {
+                self.pmt_pid = parse_pat(payload).or(self.pmt_pid);
+            } else if Some(pid) == self.pmt_pid && payload_start {
+                self.audio_pid = parse_pmt(payload).or(self.audio_pid);
+            } else if Some(pid) == self.audio_pid {
+                if payload_start {
+                    self.flush(&mut output);
+                }
+
+                self.pes.extend_from_slice(payload);
+            }
+        }
+
+        // segments start with a new pes packet, so the last one is complete
+        self.flush(&mut output);
+
+        output
+    }
+
+    fn flush(&mut self, output: &mut Vec<u8>) {
+        if self.pes.len() > 9 && self.pes.starts_with(&[0x00, 0x00, 0x01]) {
+            let start = 9 + usize::from(self.pes[8]);
+
+            if let Some(payload) = self.pes.get(start..) {
+                output.extend_from_slice(payload);
+            }
+        }
+
+        self.pes.clear();
+    }
+}
+
+fn section(payload: &[u8]) -> Option<&[u8]> {
+    let pointer = usize::from(*payload.first()?);
+    let table = payload.get(1 + pointer..)?;
+    let length = (usize::from(*table.get(1)? & 0x0F) << 8) | usize::from(*table.get(2)?);
+
+    // drops the trailing crc
+    table.get(..(3 + length).checked_sub(4)?)
+}
+
+fn parse_pat(payload: &[u8]) -> Option<u16> {
+    let table = section(payload)?;
+
+    table.get(8..)?.chunks_exact(4).find_map(|program| {
+        let number = (u16::from(program[0]) << 8) | u16::from(program[1]);
+
+        (number != 0).then(|| (u16::from(program[2] & 0x1F) << 8) | u16::from(program[3]))
+    })
+}
+
+fn parse_pmt(payload: &[u8]) -> Option<u16> {
+    let table = section(payload)?;
+    let info_length = (usize::from(*table.get(10)? & 0x0F) << 8) | usize::from(*table.get(11)?);
+
+    let mut index = 12 + info_length;
+
+    while let Some(stream) = table.get(index..index + 5) {
+        let pid = (u16::from(stream[1] & 0x1F) << 8) | u16::from(stream[2]);
+
+        if stream[0] == STREAM_TYPE_ADTS_AAC {
+            return Some(pid);
+        }
+
+        index += 5 + ((usize::from(stream[3] & 0x0F) << 8) | usize::from(stream[4]));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_smallest_audio_variant() {
+        let master = r#"#EXTM3U
+#EXT-X-INDEPENDENT-SEGMENTS
+#EXT-X-STREAM-INF:BANDWIDTH=290288,CODECS="mp4a.40.5,avc1.4d4015",RESOLUTION=256x144,FRAME-RATE=30
+https://manifest.googlevideo.com/api/manifest/hls_playlist/itag/91/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=150000,CODECS="mp4a.40.2"
+audio/high.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS="mp4a.40.5"
+
+audio/low.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=4617984,CODECS="mp4a.40.2,avc1.640028",RESOLUTION=1920x1080
+https://manifest.googlevideo.com/api/manifest/hls_playlist/itag/96/index.m3u8
+"#;
+
+        let variants = parse_master(master);
+
+        assert_eq!(variants.len(), 4);
+        assert!(!variants[0].audio_only);
+        assert!(variants[1].audio_only);
+
+        assert_eq!(pick_variant(variants).unwrap().uri, "audio/low.m3u8");
+    }
+
+    #[test]
+    fn falls_back_to_the_smallest_video_variant() {
+        let master = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=4617984,CODECS=\"mp4a.40.2,avc1.640028\"
+1080p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=290288,CODECS=\"mp4a.40.5,avc1.4d4015\"
+144p.m3u8
+#EXT-X-STREAM-INF:CODECS=\"mp4a.40.2,avc1.4d401e\"
+unknown.m3u8
+";
+
+        assert_eq!(pick_variant(parse_master(master)).unwrap().uri, "144p.m3u8");
+        assert!(pick_variant(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn parses_live_media_playlists() {
+        let media = "#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-TARGETDURATION:5
+#EXT-X-MEDIA-SEQUENCE:7741
+#EXTINF:5.005,
+sq/7741/segment.ts
+#EXTINF:5.005,
+sq/7742/segment.ts
+#EXT-X-DISCONTINUITY
+#EXTINF:5.005,
+sq/7743/segment.ts
+#EXTINF:5.005,
+sq/7744/segment.ts
+";
+
+        let playlist = parse_media(media);
+
+        assert_eq!(playlist.target_duration, Duration::from_secs(5));
+        assert!(!playlist.ended);
+        assert_eq!(
+            playlist
+                .segments
+                .iter()
+                .map(|segment| (
+                    segment.sequence,
+                    segment.discontinuity,
+                    segment.uri.as_str()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (7741, false, "sq/7741/segment.ts"),
+                (7742, false, "sq/7742/segment.ts"),
+                (7743, true, "sq/7743/segment.ts"),
+                (7744, false, "sq/7744/segment.ts"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_ended_media_playlists() {
+        let media = "#EXTM3U
+#EXT-X-TARGETDURATION:2.5
+#EXTINF:2.5,
+0.ts
+#EXTINF:1.2,
+1.ts
+#EXT-X-ENDLIST
+";
+
+        let playlist = parse_media(media);
+
+        assert_eq!(playlist.target_duration, Duration::from_millis(2500));
+        assert!(playlist.ended);
+        assert_eq!(playlist.segments.len(), 2);
+        assert_eq!(playlist.segments[0].sequence, 0);
+        assert_eq!(playlist.segments[1].sequence, 1);
+    }
+
+    #[test]
+    fn demuxes_ts_segments_to_adts() {
+        let segment = include_bytes!("../../tests/fixtures/hls/segment.ts");
+        let audio = include_bytes!("../../tests/fixtures/hls/segment.aac");
+
+        let mut demuxer = TsDemuxer::default();
+
+        assert!(demuxer.demux(segment) == audio);
+        assert_eq!(demuxer.pmt_pid, Some(0x1000));
+        assert_eq!(demuxer.audio_pid, Some(0x101));
+
+        // the tables are remembered for segments that don't repeat them
+        let without_tables = &segment[TS_PACKET_SIZE * 2..];
+
+        assert!(demuxer.demux(without_tables) == audio);
+    }
+
+    #[test]
+    fn passes_non_ts_segments_through() {
+        let audio = include_bytes!("../../tests/fixtures/hls/segment.aac");
+
+        assert!(TsDemuxer::default().demux(audio) == audio);
+    }
+}
@@ -0,0 +1,113 @@
+use super::config::{LogFormat, LogRotation, LoggingConfig};
+use std::env;
+use tracing::level_filters::LevelFilter;
+use tracing_appender::{
+    non_blocking::WorkerGuard, rolling::RollingFileAppender, rolling::Rotation,
+};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry,
+    fmt::{self, MakeWriter},
+    layer::SubscriberExt,
+};
+
+static DEFAULT_LEVEL: LevelFilter = LevelFilter::INFO;
+static DEFAULT_FILE_PREFIX: &str = "ibuki.log";
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+fn make_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let layer = fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_thread_ids(true)
+        .with_thread_names(true)
+        .with_target(true);
+
+    match format {
+        LogFormat::Pretty => layer
+            .pretty()
+            .with_file(true)
+            .with_line_number(true)
+            .boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+/**
+ * Environment variables take priority over the config, `RUST_LOG` follows the usual EnvFilter syntax
+ */
+fn make_filter(config: Option<&LoggingConfig>) -> EnvFilter {
+    let level = env::var("IBUKI_LOG_LEVEL")
+        .ok()
+        .or_else(|| config.and_then(|config| config.level.clone()))
+        .and_then(|level| {
+            level
+                .parse::<LevelFilter>()
+                .inspect_err(|_| eprintln!("Invalid log level [{level}], using the default"))
+                .ok()
+        })
+        .unwrap_or(DEFAULT_LEVEL);
+
+    let filters = env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .or_else(|| config.and_then(|config| config.filters.clone()));
+
+    // the level goes first so per module directives can override it
+    match filters {
+        Some(filters) => EnvFilter::builder().parse_lossy(format!("{level},{filters}")),
+        None => EnvFilter::builder()
+            .with_default_directive(level.into())
+            .parse_lossy(""),
+    }
+}
+
+/**
+ * Sets the global logger, the returned guard flushes the log file once dropped hence it should live until exit
+ */
+pub fn init(config: Option<&LoggingConfig>) -> Option<WorkerGuard> {
+    let format = env::var("IBUKI_LOG_FORMAT")
+        .ok()
+        .and_then(|format| serde_json::from_value(serde_json::Value::String(format)).ok())
+        .or_else(|| config.and_then(|config| config.format))
+        .unwrap_or_default();
+
+    let mut layers: Vec<BoxedLayer> = vec![make_layer(format, std::io::stdout, true)];
+    let mut guard = None;
+
+    if let Some(file) = config.and_then(|config| config.file.as_ref()) {
+        let rotation = match file.rotation.unwrap_or_default() {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        };
+
+        let appender = RollingFileAppender::new(
+            rotation,
+            &file.directory,
+            file.prefix.as_deref().unwrap_or(DEFAULT_FILE_PREFIX),
+        );
+
+        let (writer, worker_guard) = tracing_appender::non_blocking(appender);
+
+        layers.push(make_layer(format, writer, false));
+
+        let _ = guard.insert(worker_guard);
+    }
+
+    let subscriber = tracing_subscriber::registry()
+        .with(layers)
+        .with(make_filter(config));
+
+    tracing::subscriber::set_global_default(subscriber).expect("Failed to set global logger");
+
+    guard
+}
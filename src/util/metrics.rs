@@ -0,0 +1,221 @@
+//
+// Prometheus metrics, only collected when the `metrics` feature is enabled.
+// Without the feature every recorder below is a no-op so call sites don't need to care
+//
+
+use crate::models::ApiFrameStats;
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+mod collector {
+    use crate::{ALLOCATOR, Clients, Scheduler};
+    use prometheus::{
+        Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+        TextEncoder,
+    };
+    use std::sync::LazyLock;
+
+    pub static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::new);
+
+    fn register<T: prometheus::core::Collector + Clone + 'static>(collector: T) -> T {
+        REGISTRY
+            .register(Box::new(collector.clone()))
+            .expect("Failed to register a metric");
+        collector
+    }
+
+    pub static PLAYERS: LazyLock<IntGauge> =
+        LazyLock::new(|| register(IntGauge::new("ibuki_players", "Players on this node").unwrap()));
+
+    pub static PLAYING_PLAYERS: LazyLock<IntGauge> = LazyLock::new(|| {
+        register(
+            IntGauge::new("ibuki_playing_players", "Players that are playing a track").unwrap(),
+        )
+    });
+
+    pub static HEAP_USED: LazyLock<IntGauge> = LazyLock::new(|| {
+        register(IntGauge::new("ibuki_heap_used_bytes", "Heap bytes currently allocated").unwrap())
+    });
+
+    pub static HEAP_LIMIT: LazyLock<IntGauge> = LazyLock::new(|| {
+        register(IntGauge::new("ibuki_heap_limit_bytes", "Heap allocation limit").unwrap())
+    });
+
+    pub static WEBSOCKET_SESSIONS: LazyLock<IntGauge> = LazyLock::new(|| {
+        register(
+            IntGauge::new(
+                "ibuki_websocket_sessions",
+                "Websocket sessions on this node",
+            )
+            .unwrap(),
+        )
+    });
+
+    pub static WEBSOCKET_RESUMES: LazyLock<IntCounter> = LazyLock::new(|| {
+        register(
+            IntCounter::new(
+                "ibuki_websocket_resumes_total",
+                "Websocket sessions resumed",
+            )
+            .unwrap(),
+        )
+    });
+
+    pub static RESOLVER_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+        register(
+            HistogramVec::new(
+                HistogramOpts::new(
+                    "ibuki_resolver_duration_seconds",
+                    "Time taken by a source to resolve a query",
+                ),
+                &["source"],
+            )
+            .unwrap(),
+        )
+    });
+
+    pub static RESOLVER_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        register(
+            IntCounterVec::new(
+                Opts::new(
+                    "ibuki_resolver_errors_total",
+                    "Queries a source failed to resolve",
+                ),
+                &["source"],
+            )
+            .unwrap(),
+        )
+    });
+
+    pub static TRACK_STARTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        register(
+            IntCounterVec::new(
+                Opts::new("ibuki_track_starts_total", "Tracks that started playing"),
+                &["source"],
+            )
+            .unwrap(),
+        )
+    });
+
+    pub static TRACK_ENDS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        register(
+            IntCounterVec::new(
+                Opts::new("ibuki_track_ends_total", "Tracks that ended playing"),
+                &["reason"],
+            )
+            .unwrap(),
+        )
+    });
+
+    pub static FRAMES_SENT: LazyLock<IntGauge> = LazyLock::new(|| {
+        register(
+            IntGauge::new(
+                "ibuki_frames_sent",
+                "Frames sent per playing player and minute",
+            )
+            .unwrap(),
+        )
+    });
+
+    pub static FRAMES_NULLED: LazyLock<IntGauge> = LazyLock::new(|| {
+        register(
+            IntGauge::new(
+                "ibuki_frames_nulled",
+                "Silent frames per playing player and minute",
+            )
+            .unwrap(),
+        )
+    });
+
+    pub static FRAMES_DEFICIT: LazyLock<IntGauge> = LazyLock::new(|| {
+        register(
+            IntGauge::new(
+                "ibuki_frames_deficit",
+                "Frames missing per playing player and minute",
+            )
+            .unwrap(),
+        )
+    });
+
+    pub fn render() -> Result<String, prometheus::Error> {
+        PLAYERS.set(Scheduler.total_tasks() as i64);
+        PLAYING_PLAYERS.set(Scheduler.live_tasks() as i64);
+        HEAP_USED.set(ALLOCATOR.allocated() as i64);
+        HEAP_LIMIT.set(ALLOCATOR.limit().min(i64::MAX as usize) as i64);
+        WEBSOCKET_SESSIONS.set(Clients.len() as i64);
+
+        // counters with labels only show up once used, so make sure the unlabeled ones exist
+        LazyLock::force(&WEBSOCKET_RESUMES);
+        LazyLock::force(&RESOLVER_DURATION);
+        LazyLock::force(&RESOLVER_ERRORS);
+        LazyLock::force(&TRACK_STARTS);
+        LazyLock::force(&TRACK_ENDS);
+        LazyLock::force(&FRAMES_SENT);
+        LazyLock::force(&FRAMES_NULLED);
+        LazyLock::force(&FRAMES_DEFICIT);
+
+        let mut buffer = Vec::new();
+
+        TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer)?;
+
+        String::from_utf8(buffer).map_err(|error| prometheus::Error::Msg(error.to_string()))
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use collector::render;
+
+#[cfg(feature = "metrics")]
+pub fn observe_resolve(source: &str, elapsed: Duration, success: bool) {
+    collector::RESOLVER_DURATION
+        .with_label_values(&[source])
+        .observe(elapsed.as_secs_f64());
+
+    if !success {
+        collector::RESOLVER_ERRORS
+            .with_label_values(&[source])
+            .inc();
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn observe_resolve(_: &str, _: Duration, _: bool) {}
+
+#[cfg(feature = "metrics")]
+pub fn track_start(source: &str) {
+    collector::TRACK_STARTS.with_label_values(&[source]).inc();
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn track_start(_: &str) {}
+
+#[cfg(feature = "metrics")]
+pub fn track_end(reason: &str) {
+    collector::TRACK_ENDS.with_label_values(&[reason]).inc();
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn track_end(_: &str) {}
+
+#[cfg(feature = "metrics")]
+pub fn websocket_resume() {
+    collector::WEBSOCKET_RESUMES.inc();
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn websocket_resume() {}
+
+/**
+ * Set from the same sample that is sent as frameStats, players that aren't playing report zeros
+ */
+#[cfg(feature = "metrics")]
+pub fn frame_stats(stats: Option<&ApiFrameStats>) {
+    let stats = stats.cloned().unwrap_or_default();
+
+    collector::FRAMES_SENT.set(stats.sent as i64);
+    collector::FRAMES_NULLED.set(stats.nulled as i64);
+    collector::FRAMES_DEFICIT.set(stats.deficit as i64);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn frame_stats(_: Option<&ApiFrameStats>) {}
@@ -4,6 +4,11 @@ pub mod decoder;
 pub mod encoder;
 pub mod errors;
 pub mod headers;
+pub mod hls;
+pub mod logger;
+pub mod metrics;
+pub mod reload;
 pub mod seek;
 pub mod source;
+pub mod tenant;
 pub mod url;
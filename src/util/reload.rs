@@ -0,0 +1,103 @@
+use super::{errors::ConfigError, source::sync_sources, tenant::sync_tenants};
+use crate::{Config, models::ApiReloadReport, voice::player::refresh_global_events};
+use std::sync::{Arc, LazyLock};
+use tokio::sync::Mutex;
+
+static RELOAD_LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+/**
+ * Reloads the config and applies what can be applied without a restart. Websocket clients and their players are left untouched
+ */
+pub async fn reload() -> Result<ApiReloadReport, ConfigError> {
+    let _guard = RELOAD_LOCK.lock().await;
+
+    let config = Config::load()?;
+    let previous = Config.load_full();
+
+    let mut report = ApiReloadReport::default();
+
+    let live = [
+        (
+            "authorization",
+            previous.authorization != config.authorization,
+        ),
+        ("apiKeys", previous.api_keys != config.api_keys),
+        (
+            "youtubeConfig",
+            previous.youtube_config != config.youtube_config,
+        ),
+        (
+            "deezerConfig",
+            previous.deezer_config != config.deezer_config,
+        ),
+        ("ytdlpConfig", previous.ytdlp_config != config.ytdlp_config),
+        ("httpConfig", previous.http_config != config.http_config),
+        ("searchOrder", previous.search_order != config.search_order),
+        (
+            "metricsConfig",
+            previous.metrics_config != config.metrics_config,
+        ),
+        (
+            "playerUpdateSecs",
+            previous.player_update_secs != config.player_update_secs,
+        ),
+        (
+            "statusUpdateSecs",
+            previous.status_update_secs != config.status_update_secs,
+        ),
+    ];
+
+    let restart_only = [
+        ("port", previous.port != config.port),
+        ("address", previous.address != config.address),
+        (
+            "loggingConfig",
+            previous.logging_config != config.logging_config,
+        ),
+        (
+            "rateLimitConfig",
+            previous.rate_limit_config != config.rate_limit_config,
+        ),
+    ];
+
+    report.applied = live
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+    report.not_applied = restart_only
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+    Config.store(Arc::new(config));
+
+    let config = Config.load_full();
+
+    sync_tenants(&config);
+    sync_sources(Some(&previous), &config).await;
+
+    if previous.player_update_secs != config.player_update_secs {
+        refresh_global_events().await;
+    }
+
+    tracing::info!(
+        "Config reloaded. [Applied: {}]",
+        if report.applied.is_empty() {
+            String::from("None")
+        } else {
+            report.applied.join(", ")
+        }
+    );
+
+    if !report.not_applied.is_empty() {
+        tracing::warn!(
+            "Some changed settings can't be applied live and need a restart: [{}]",
+            report.not_applied.join(", ")
+        );
+    }
+
+    Ok(report)
+}
@@ -23,12 +23,18 @@ pub fn create_vec_with_capacity<T>(downloaded_total_bytes: Option<usize>) -> Vec
     Vec::with_capacity(initial_capacity)
 }
 
+/**
+ * Opens the stream again starting at a byte offset, used when a stream url stops working mid playback
+ */
+pub type StreamRefresh = Box<dyn FnMut(usize) -> IoResult<Box<dyn MediaSource>> + Send + Sync>;
+
 pub struct SeekableSource {
     source: Box<dyn MediaSource>,
     position: usize,
     downloaded: Vec<u8>,
     downloaded_bytes: usize,
     total_bytes: Option<usize>,
+    refresh: Option<StreamRefresh>,
 }
 
 impl Read for SeekableSource {
@@ -39,7 +45,7 @@ impl Read for SeekableSource {
             self.position += bytes_read;
             self.downloaded_bytes += bytes_read;
 
-            return Ok(self.downloaded_bytes);
+            return Ok(bytes_read);
         };
 
         if self.position < self.downloaded_bytes {
@@ -59,7 +65,17 @@ impl Read for SeekableSource {
             return Ok(bytes_read);
         }
 
-        let bytes_read = block_in_place(|| self.source.read(buf))?;
+        let bytes_read = match block_in_place(|| self.source.read(buf)) {
+            // the stream ended before its length, the url most likely expired
+            Ok(0) if self.is_truncated() => self.refresh_source(buf)?,
+            Ok(bytes_read) => bytes_read,
+            Err(error) if self.refresh.is_some() => {
+                tracing::warn!("Stream read failed, refreshing the stream: {}", error);
+
+                self.refresh_source(buf)?
+            }
+            Err(error) => return Err(error),
+        };
 
         if bytes_read == 0 {
             return Ok(bytes_read);
@@ -128,9 +144,35 @@ impl SeekableSource {
             downloaded: create_vec_with_capacity(total_bytes),
             downloaded_bytes: 0,
             total_bytes,
+            refresh: None,
         }
     }
 
+    pub fn with_refresh(mut self, refresh: StreamRefresh) -> Self {
+        self.refresh = Some(refresh);
+        self
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.refresh.is_some()
+            && self
+                .total_bytes
+                .is_some_and(|total_bytes| self.downloaded_bytes < total_bytes)
+    }
+
+    /**
+     * Replaces the source with one starting where the downloaded bytes end. Only tried once per failed read
+     */
+    fn refresh_source(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let Some(refresh) = self.refresh.as_mut() else {
+            return Ok(0);
+        };
+
+        self.source = refresh(self.downloaded_bytes)?;
+
+        block_in_place(|| self.source.read(buf))
+    }
+
     pub fn into_audio_stream(self, hint: Option<Hint>) -> AudioStream<Box<dyn MediaSource>> {
         AudioStream {
             input: Box::new(self) as Box<dyn MediaSource>,
@@ -138,3 +180,267 @@ impl SeekableSource {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::{Body, Bytes},
+        extract::State,
+        http::{
+            HeaderMap, StatusCode,
+            header::{CONTENT_LENGTH, CONTENT_RANGE, RANGE},
+        },
+        response::{IntoResponse, Response},
+        routing,
+    };
+    use futures::StreamExt;
+    use reqwest::Client;
+    use songbird::input::{Compose, HttpRequest};
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use tokio::{net::TcpListener, runtime::Handle, time::sleep};
+
+    const LENGTH: usize = 200_000;
+    // how much of the body the expiring url sends before cutting the connection. More than songbird buffers ahead,
+    // otherwise the length is only known once the pending read fails
+    const SENT_BEFORE_CUT: usize = 100_000;
+
+    struct Fixture {
+        data: Vec<u8>,
+        ranges: Mutex<Vec<String>>,
+    }
+
+    fn data() -> Vec<u8> {
+        (0..LENGTH).map(|index| (index % 251) as u8).collect()
+    }
+
+    async fn expiring(State(fixture): State<Arc<Fixture>>, headers: HeaderMap) -> Response {
+        // songbird retries cut requests with a range itself, which the expired url rejects
+        if headers.contains_key(RANGE) {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+
+        let sent = Bytes::copy_from_slice(&fixture.data[..SENT_BEFORE_CUT]);
+
+        // the cut is delayed so the client gets the headers and the first bytes
+        let chunks = futures::stream::iter([Some(sent), None]).then(|chunk| async move {
+            match chunk {
+                Some(chunk) => Ok(chunk),
+                None => {
+                    sleep(Duration::from_millis(100)).await;
+                    Err(IoError::other("url expired"))
+                }
+            }
+        });
+
+        (
+            [(CONTENT_LENGTH, LENGTH.to_string())],
+            Body::from_stream(chunks),
+        )
+            .into_response()
+    }
+
+    async fn fresh(State(fixture): State<Arc<Fixture>>, headers: HeaderMap) -> Response {
+        let range = headers
+            .get(RANGE)
+            .and_then(|range| range.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        fixture.ranges.lock().unwrap().push(range.clone());
+
+        let Some(start) = range
+            .strip_prefix("bytes=")
+            .and_then(|range| range.strip_suffix('-'))
+            .and_then(|start| start.parse::<usize>().ok())
+        else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
+
+        (
+            StatusCode::PARTIAL_CONTENT,
+            [(
+                CONTENT_RANGE,
+                format!("bytes {start}-{}/{LENGTH}", LENGTH - 1),
+            )],
+            fixture.data[start..].to_vec(),
+        )
+            .into_response()
+    }
+
+    async fn serve() -> (String, Arc<Fixture>) {
+        let fixture = Arc::new(Fixture {
+            data: data(),
+            ranges: Mutex::new(Vec::new()),
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+
+        let router = Router::new()
+            .route("/expiring", routing::get(expiring))
+            .route("/fresh", routing::get(fresh))
+            .route(
+                "/forbidden",
+                routing::get(|| async { StatusCode::FORBIDDEN }),
+            )
+            .with_state(fixture.clone());
+
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        (base, fixture)
+    }
+
+    async fn open(url: String) -> Box<dyn MediaSource> {
+        HttpRequest::new(Client::builder().no_proxy().build().unwrap(), url)
+            .create_async()
+            .await
+            .unwrap()
+            .input
+    }
+
+    /**
+     * Opens `url` with a range starting at the offset, the offsets it was called with are kept
+     */
+    fn refresh_to(url: String, offsets: Arc<Mutex<Vec<usize>>>) -> StreamRefresh {
+        let handle = Handle::current();
+
+        Box::new(move |offset| {
+            offsets.lock().unwrap().push(offset);
+
+            let mut headers = reqwest::header::HeaderMap::new();
+
+            headers.insert(RANGE, format!("bytes={offset}-").parse().unwrap());
+
+            let mut request = HttpRequest::new_with_headers(
+                Client::builder().no_proxy().build().unwrap(),
+                url.clone(),
+                headers,
+            );
+
+            block_in_place(|| handle.block_on(request.create_async()))
+                .map(|stream| stream.input)
+                .map_err(IoError::other)
+        })
+    }
+
+    fn read_to_end(source: &mut SeekableSource) -> IoResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+
+        source.read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reopens_cut_streams_where_they_stopped() {
+        let (base, fixture) = serve().await;
+        let offsets = Arc::new(Mutex::new(Vec::new()));
+
+        let mut source = SeekableSource::new(open(format!("{base}/expiring")).await)
+            .with_refresh(refresh_to(format!("{base}/fresh"), offsets.clone()));
+
+        assert_eq!(read_to_end(&mut source).unwrap(), fixture.data);
+
+        let offsets = offsets.lock().unwrap().clone();
+
+        assert_eq!(offsets, [SENT_BEFORE_CUT]);
+        assert_eq!(
+            *fixture.ranges.lock().unwrap(),
+            [format!("bytes={SENT_BEFORE_CUT}-")]
+        );
+
+        // everything read before and after the refresh stays seekable
+        source.seek(SeekFrom::Start(10)).unwrap();
+
+        let mut bytes = [0; 4];
+
+        source.read_exact(&mut bytes).unwrap();
+
+        assert_eq!(bytes, fixture.data[10..14]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reports_failed_refreshes() {
+        let (base, _) = serve().await;
+        let offsets = Arc::new(Mutex::new(Vec::new()));
+
+        let mut source = SeekableSource::new(open(format!("{base}/expiring")).await)
+            .with_refresh(refresh_to(format!("{base}/forbidden"), offsets.clone()));
+
+        assert!(read_to_end(&mut source).is_err());
+        assert_eq!(*offsets.lock().unwrap(), [SENT_BEFORE_CUT]);
+    }
+
+    /**
+     * Ends cleanly before `length` bytes while claiming to be longer, like a server closing an expired stream early
+     */
+    struct Truncated {
+        data: Cursor<Vec<u8>>,
+        length: u64,
+    }
+
+    impl Read for Truncated {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for Truncated {
+        fn seek(&mut self, position: SeekFrom) -> IoResult<u64> {
+            self.data.seek(position)
+        }
+    }
+
+    impl MediaSource for Truncated {
+        fn is_seekable(&self) -> bool {
+            false
+        }
+
+        fn byte_len(&self) -> Option<u64> {
+            Some(self.length)
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reopens_streams_that_end_early() {
+        let data = data();
+        let offsets = Arc::new(Mutex::new(Vec::new()));
+
+        let refresh_data = data.clone();
+        let refresh_offsets = offsets.clone();
+
+        let mut source = SeekableSource::new(Box::new(Truncated {
+            data: Cursor::new(data[..SENT_BEFORE_CUT].to_vec()),
+            length: LENGTH as u64,
+        }))
+        .with_refresh(Box::new(move |offset| {
+            refresh_offsets.lock().unwrap().push(offset);
+
+            Ok(Box::new(Truncated {
+                data: Cursor::new(refresh_data[offset..].to_vec()),
+                length: (LENGTH - offset) as u64,
+            }))
+        }));
+
+        assert_eq!(read_to_end(&mut source).unwrap(), data);
+        assert_eq!(*offsets.lock().unwrap(), [SENT_BEFORE_CUT]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn streams_without_a_refresh_just_end() {
+        let data = data();
+
+        let mut source = SeekableSource::new(Box::new(Truncated {
+            data: Cursor::new(data[..SENT_BEFORE_CUT].to_vec()),
+            length: LENGTH as u64,
+        }));
+
+        assert_eq!(read_to_end(&mut source).unwrap(), data[..SENT_BEFORE_CUT]);
+    }
+}
@@ -1,47 +1,329 @@
+use super::config::Config;
 use super::errors::ResolverError;
 use crate::{
-    AvailableSources,
-    models::{ApiTrack, ApiTrackResult},
-    source::{deezer::source::Deezer, http::Http, youtube::Youtube},
+    AvailableSources, Reqwest, SearchOrder, SourceStates,
+    models::{ApiSearchResult, ApiSearchType, ApiSourceInfo, ApiTrack, ApiTrackResult},
+    source::{deezer::source::Deezer, http::Http, youtube::Youtube, ytdlp::Ytdlp},
 };
+use async_trait::async_trait;
+use regex::Regex;
 use reqwest::Client;
 use songbird::tracks::Track;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use symphonia::core::io::MediaSource;
 
 pub enum Query {
     Url(String),
     Search(String),
 }
 
-pub enum Sources {
-    Youtube(Youtube),
-    Deezer(Deezer),
-    Http(Http),
-}
-
-pub trait Source {
-    fn new(client: Option<Client>) -> Self;
-
+/**
+ * A source that tracks can be loaded and played from. Implement this and register it into `AvailableSources` to add a new one
+ */
+#[async_trait]
+pub trait Source: Send + Sync {
     fn get_name(&self) -> &'static str;
 
     fn get_client(&self) -> Client;
 
-    fn parse_query(&self, url: &str) -> Option<Query>;
+    /**
+     * Prefixes of queries this source handles, the first one is used when falling back to this source for a search
+     */
+    fn search_prefixes(&self) -> &[&'static str];
+
+    /**
+     * Patterns of urls this source can load
+     */
+    fn url_patterns(&self) -> &[Regex];
+
+    /**
+     * Sources are tried from the lowest priority up. Sources that accept any url use a higher one so more specific sources get the first try
+     */
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    fn search_prefix(&self) -> Option<&'static str> {
+        self.search_prefixes().first().copied()
+    }
+
+    fn parse_query(&self, query: &str) -> Option<Query> {
+        if self
+            .search_prefixes()
+            .iter()
+            .any(|prefix| query.starts_with(prefix))
+        {
+            return Some(Query::Search(query.to_string()));
+        }
+
+        if self
+            .url_patterns()
+            .iter()
+            .any(|pattern| pattern.is_match(query))
+        {
+            return Some(Query::Url(query.to_string()));
+        }
+
+        None
+    }
 
     async fn resolve(&self, query: Query) -> Result<ApiTrackResult, ResolverError>;
 
+    /**
+     * Typed results for autocomplete, sources that don't support it return None
+     */
+    async fn load_search(
+        &self,
+        _query: &str,
+        _types: &[ApiSearchType],
+    ) -> Result<Option<ApiSearchResult>, ResolverError> {
+        Ok(None)
+    }
+
     async fn make_playable(&self, track: ApiTrack) -> Result<Track, ResolverError>;
+
+    /**
+     * Opens the track's stream again at a byte offset, sources that can't refresh their streams return None
+     */
+    async fn refresh_stream(
+        &self,
+        _track: &ApiTrack,
+        _offset: usize,
+    ) -> Result<Option<Box<dyn MediaSource>>, ResolverError> {
+        Ok(None)
+    }
+}
+
+/**
+ * Runtime state of a registered source, kept across config reloads
+ */
+pub struct SourceState {
+    pub enabled: bool,
+    pub failed: bool,
+    pub successes: u64,
+    pub failures: u64,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<u64>,
+}
+
+impl Default for SourceState {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failed: false,
+            successes: 0,
+            failures: 0,
+            last_error: None,
+            last_error_at: None,
+        }
+    }
+}
+
+/**
+ * Sources that can handle the identifier along with the parsed query, more specific sources first
+ */
+pub fn find_sources(identifier: &str) -> Vec<(Arc<dyn Source>, Query)> {
+    let mut found: Vec<(Arc<dyn Source>, Query)> = AvailableSources
+        .iter()
+        .filter_map(|source| {
+            let query = source.parse_query(identifier)?;
+            Some((source.value().clone(), query))
+        })
+        .collect();
+
+    found.sort_by_key(|(source, _)| source.priority());
+
+    found
 }
 
 impl ApiTrack {
     pub async fn make_playable(self) -> Result<Track, ResolverError> {
-        let Some(client) = AvailableSources.get(&self.info.source_name) else {
+        let name = self.info.source_name.clone();
+
+        let Some(source) = AvailableSources.get(&name).map(|source| source.clone()) else {
             return Err(ResolverError::InputNotSupported);
         };
 
-        match &*client {
-            Sources::Youtube(src) => src.make_playable(self).await,
-            Sources::Deezer(src) => src.make_playable(self).await,
-            Sources::Http(src) => src.make_playable(self).await,
+        if !is_source_enabled(&name) {
+            return Err(ResolverError::SourceDisabled(name));
+        }
+
+        let result = source.make_playable(self).await;
+
+        record_result(&name, result.as_ref().err());
+
+        result
+    }
+}
+
+pub fn is_source_enabled(name: &str) -> bool {
+    SourceStates.get(name).is_none_or(|state| state.enabled)
+}
+
+/**
+ * Returns false if the source is not registered
+ */
+pub fn set_source_enabled(name: &str, enabled: bool) -> bool {
+    let Some(mut state) = SourceStates.get_mut(name) else {
+        return false;
+    };
+
+    state.enabled = enabled;
+
+    tracing::info!(
+        "Source [{}] is now {}",
+        name,
+        if enabled { "enabled" } else { "disabled" }
+    );
+
+    true
+}
+
+/**
+ * Disables a source that can't work in its current state, like when its credentials are rejected
+ */
+pub fn fail_source(name: &str, error: &ResolverError) {
+    let Some(mut state) = SourceStates.get_mut(name) else {
+        return;
+    };
+
+    if !state.failed {
+        tracing::error!("Disabling source [{}]: {}", name, error);
+    }
+
+    state.enabled = false;
+    state.failed = true;
+
+    drop(state);
+
+    record_result(name, Some(error));
+}
+
+pub fn record_result(name: &str, error: Option<&ResolverError>) {
+    let Some(mut state) = SourceStates.get_mut(name) else {
+        return;
+    };
+
+    match error {
+        Some(error) => {
+            state.failures += 1;
+            state.last_error = Some(error.to_string());
+            state.last_error_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|duration| duration.as_millis() as u64);
+        }
+        None => state.successes += 1,
+    }
+}
+
+pub fn source_info(name: &str) -> Option<ApiSourceInfo> {
+    let state = SourceStates.get(name)?;
+
+    let total = state.successes + state.failures;
+
+    Some(ApiSourceInfo {
+        name: name.to_string(),
+        enabled: state.enabled,
+        failed: state.failed,
+        successes: state.successes,
+        failures: state.failures,
+        success_rate: (total > 0).then(|| state.successes as f64 / total as f64),
+        last_error: state.last_error.clone(),
+        last_error_at: state.last_error_at,
+    })
+}
+
+/**
+ * The order sources are tried in when a search returns nothing or fails. Defaults to youtube, ytdlp then deezer
+ */
+fn search_order(config: &Config) -> Vec<String> {
+    config
+        .search_order
+        .as_ref()
+        .map(|order| order.iter().map(|name| name.to_lowercase()).collect())
+        .unwrap_or_else(|| {
+            vec![
+                String::from("youtube"),
+                String::from("ytdlp"),
+                String::from("deezer"),
+            ]
+        })
+}
+
+pub fn register_source(source: Arc<dyn Source>) {
+    let name = source.get_name();
+
+    AvailableSources.insert(name.to_string(), source);
+
+    let mut state = SourceStates.entry(name.to_string()).or_default();
+
+    // a fresh instance gets another chance, e.g. after the credentials were fixed and reloaded
+    if state.failed {
+        state.failed = false;
+        state.enabled = true;
+    }
+
+    drop(state);
+
+    tracing::info!("Registered [{}] into sources list", name);
+}
+
+pub fn unregister_source(name: &str) {
+    if AvailableSources.remove(name).is_some() {
+        SourceStates.remove(name);
+
+        tracing::info!("Removed [{}] from sources list", name);
+    }
+}
+
+/**
+ * Registers, replaces or removes sources so they match the config. Sources with unchanged config are left as is
+ */
+pub async fn sync_sources(previous: Option<&Config>, config: &Config) {
+    if previous.is_none_or(|previous| previous.youtube_config != config.youtube_config) {
+        match config.youtube_config {
+            Some(_) => {
+                let client = Arc::new(Youtube::new(Some(Reqwest.clone())));
+
+                register_source(client.clone());
+
+                client.init().await;
+            }
+            None => unregister_source("youtube"),
+        }
+    }
+
+    if previous.is_none_or(|previous| previous.deezer_config != config.deezer_config) {
+        match config.deezer_config.as_ref() {
+            Some(deezer_config) => {
+                let client = Arc::new(Deezer::new(Some(Reqwest.clone()), deezer_config));
+
+                // registered first so a rejected arl can mark the source as failed
+                register_source(client.clone());
+
+                client.init().await;
+            }
+            None => unregister_source("deezer"),
+        }
+    }
+
+    if previous.is_none_or(|previous| previous.ytdlp_config != config.ytdlp_config) {
+        match config.ytdlp_config {
+            Some(_) => register_source(Arc::new(Ytdlp::new(Some(Reqwest.clone())))),
+            None => unregister_source("ytdlp"),
         }
     }
+
+    if previous.is_none_or(|previous| previous.http_config != config.http_config) {
+        match config.http_config {
+            Some(_) => register_source(Arc::new(Http::new(Some(Reqwest.clone())))),
+            None => unregister_source("http"),
+        }
+    }
+
+    if previous.is_none_or(|previous| previous.search_order != config.search_order) {
+        SearchOrder.store(Arc::new(search_order(config)));
+    }
 }
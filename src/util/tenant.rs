@@ -0,0 +1,165 @@
+use super::config::{ApiKeyConfig, Config};
+use crate::models::ApiTenantInfo;
+use crate::{Clients, Tenants};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+pub static DEFAULT_TENANT: &str = "default";
+
+static NEXT_TENANT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
+pub struct Tenant {
+    /**
+     * Unique per tenant and kept across reloads, sessions belong to the tenant with the same id
+     */
+    pub id: u64,
+    pub name: String,
+    pub allowed_user_ids: Option<Vec<u64>>,
+    pub max_players: Option<u32>,
+    pub sources: Option<Vec<String>>,
+    pub admin: bool,
+    pub players: Arc<AtomicU32>,
+}
+
+impl Tenant {
+    pub fn new(config: &ApiKeyConfig) -> Self {
+        Self {
+            id: NEXT_TENANT_ID.fetch_add(1, Ordering::Relaxed),
+            name: config.name.clone(),
+            allowed_user_ids: config.allowed_user_ids.clone(),
+            max_players: config.max_players,
+            sources: config
+                .sources
+                .as_ref()
+                .map(|sources| sources.iter().map(|name| name.to_lowercase()).collect()),
+            admin: false,
+            players: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /**
+     * The tenant made from the top level authorization key, which has no limits and can use admin routes
+     */
+    pub fn unrestricted() -> Self {
+        Self {
+            id: NEXT_TENANT_ID.fetch_add(1, Ordering::Relaxed),
+            name: String::from(DEFAULT_TENANT),
+            allowed_user_ids: None,
+            max_players: None,
+            sources: None,
+            admin: true,
+            players: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    pub fn owns(&self, other: &Tenant) -> bool {
+        self.id == other.id
+    }
+
+    pub fn is_user_allowed(&self, user_id: u64) -> bool {
+        self.allowed_user_ids
+            .as_ref()
+            .is_none_or(|ids| ids.contains(&user_id))
+    }
+
+    pub fn is_source_allowed(&self, source: &str) -> bool {
+        self.sources
+            .as_ref()
+            .is_none_or(|sources| sources.iter().any(|name| name.eq_ignore_ascii_case(source)))
+    }
+
+    /**
+     * Reserves a player for this tenant, the reservation is released once the returned slot is dropped
+     */
+    pub fn acquire_player(self: &Arc<Self>) -> Option<PlayerSlot> {
+        let max = self.max_players.unwrap_or(u32::MAX);
+
+        self.players
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count < max).then_some(count + 1)
+            })
+            .ok()?;
+
+        Some(PlayerSlot {
+            tenant: self.clone(),
+        })
+    }
+}
+
+pub struct PlayerSlot {
+    pub tenant: Arc<Tenant>,
+}
+
+impl Drop for PlayerSlot {
+    fn drop(&mut self) {
+        self.tenant.players.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/**
+ * Makes the tenants list match the config. Ids and player counts are carried over by tenant name, which the config keeps unique,
+ * so sessions stay reachable and limits stay accurate on reloads
+ */
+pub fn sync_tenants(config: &Config) {
+    let previous = Tenants
+        .iter()
+        .map(|tenant| (tenant.name.clone(), (tenant.id, tenant.players.clone())))
+        .collect::<HashMap<String, (u64, Arc<AtomicU32>)>>();
+
+    let mut tenants = vec![(config.authorization.clone(), Tenant::unrestricted())];
+
+    for api_key in config.api_keys.iter().flatten() {
+        if tenants.iter().any(|(key, _)| *key == api_key.key) {
+            tracing::warn!(
+                "Skipped registering [Tenant: {}] as its key is already in use",
+                api_key.name
+            );
+            continue;
+        }
+
+        tenants.push((api_key.key.clone(), Tenant::new(api_key)));
+    }
+
+    Tenants.retain(|key, _| tenants.iter().any(|(new_key, _)| new_key == key));
+
+    for (key, mut tenant) in tenants {
+        if let Some((id, players)) = previous.get(&tenant.name) {
+            tenant.id = *id;
+            tenant.players = players.clone();
+        }
+
+        if !tenant.admin {
+            tracing::info!("Registered [Tenant: {}] into tenants list", tenant.name);
+        }
+
+        Tenants.insert(key, Arc::new(tenant));
+    }
+}
+
+/**
+ * Sessions and players per tenant, sorted by name
+ */
+pub fn tenant_usage() -> Vec<ApiTenantInfo> {
+    let mut tenants = Tenants
+        .iter()
+        .map(|tenant| {
+            let sessions = Clients
+                .iter()
+                .filter(|client| client.tenant.owns(&tenant))
+                .count() as u32;
+
+            ApiTenantInfo {
+                name: tenant.name.clone(),
+                sessions,
+                players: tenant.players.load(Ordering::Acquire),
+                max_players: tenant.max_players,
+            }
+        })
+        .collect::<Vec<ApiTenantInfo>>();
+
+    tenants.sort_by(|a, b| a.name.cmp(&b.name));
+
+    tenants
+}
@@ -1,10 +1,16 @@
 use regex::Regex;
+use reqwest::Url;
 use std::sync::LazyLock;
 
-static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+pub static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"https?://(www\.)?[-a-zA-Z0-9@:%._\+~#=]{2,256}(\.[a-z]{2,4})?\b([-a-zA-Z0-9@:%_\+.~#?&//=]*)").unwrap()
 });
 
-pub fn is_url(url: &str) -> bool {
-    URL_REGEX.is_match(url)
+/**
+ * Parses the whole string as an http(s) url, unlike `URL_REGEX` which matches a url anywhere in it
+ */
+pub fn parse_http_url(url: &str) -> Option<Url> {
+    Url::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
 }
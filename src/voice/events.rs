@@ -1,28 +1,31 @@
 use super::{manager::CleanerSender, player::Player};
 use crate::models::{
     ApiNodeMessage, ApiPlayer, ApiPlayerEvents, ApiPlayerUpdate, ApiTrack, ApiTrackEnd,
-    ApiTrackStart, ApiWebSocketClosed,
+    ApiTrackEndReason, ApiTrackStart, ApiWebSocketClosed,
 };
+use crate::plugins;
+use crate::util::metrics;
 use async_trait::async_trait;
 use axum::extract::ws::{Message, Utf8Bytes};
+use dashmap::DashMap;
 use flume::WeakSender;
 use songbird::{
     CoreEvent, Driver, Event, EventContext, EventHandler, TrackEvent,
     events::context_data::DisconnectReason,
     id::{GuildId, UserId},
     model::CloseCode,
-    tracks::{TrackHandle, TrackState},
+    tracks::{PlayMode, TrackHandle, TrackState},
 };
 use std::sync::{
     Arc, Weak,
     atomic::{AtomicBool, Ordering},
 };
 use tokio::sync::Mutex;
+use tracing::Instrument;
+use uuid::Uuid;
 
 enum DataResult {
-    // probably usable in future
-    #[allow(dead_code)]
-    Track(TrackState, Arc<ApiTrack>),
+    Track(TrackState, Arc<ApiTrack>, Uuid),
     Disconnect(i32, String),
     Empty,
 }
@@ -39,6 +42,7 @@ pub struct PlayerEvent {
     pub cleaner: WeakSender<CleanerSender>,
     pub driver: Weak<Mutex<Option<Driver>>>,
     pub handle: Weak<Mutex<Option<TrackHandle>>>,
+    pub end_reasons: Weak<DashMap<Uuid, ApiTrackEndReason>>,
 }
 
 impl PlayerEvent {
@@ -54,6 +58,27 @@ impl PlayerEvent {
             cleaner: player.cleaner.clone(),
             driver: Arc::downgrade(&player.driver),
             handle: Arc::downgrade(&player.handle),
+            end_reasons: Arc::downgrade(&player.end_reasons),
+        }
+    }
+
+    /**
+     * Reasons recorded by the player win, otherwise the play mode the track ended with decides
+     */
+    pub fn end_reason(&self, uuid: &Uuid, mode: &PlayMode) -> ApiTrackEndReason {
+        // the player is gone, so it was destroyed with the track still playing
+        let Some(end_reasons) = self.end_reasons.upgrade() else {
+            return ApiTrackEndReason::Cleanup;
+        };
+
+        if let Some((_, reason)) = end_reasons.remove(uuid) {
+            return reason;
+        }
+
+        match mode {
+            PlayMode::End => ApiTrackEndReason::Finished,
+            PlayMode::Errored(_) => ApiTrackEndReason::LoadFailed,
+            _ => ApiTrackEndReason::Stopped,
         }
     }
 
@@ -134,7 +159,7 @@ impl EventHandler for PlayerEvent {
 
                 let track = handle.data::<ApiTrack>();
 
-                data_result = DataResult::Track(state, track);
+                data_result = DataResult::Track(state, track, handle.uuid());
             }
             EventContext::DriverDisconnect(info) => {
                 let (code, message) = {
@@ -166,9 +191,18 @@ impl EventHandler for PlayerEvent {
 
         let player_event = self.clone();
 
-        tokio::spawn(async move {
-            handle_player_event(player_event, data_result).await;
-        });
+        let span = tracing::info_span!(
+            "player",
+            guild_id = %self.guild_id,
+            user_id = %self.user_id
+        );
+
+        tokio::spawn(
+            async move {
+                handle_player_event(player_event, data_result).await;
+            }
+            .instrument(span),
+        );
 
         None
     }
@@ -197,6 +231,8 @@ async fn handle_player_event(player_event: PlayerEvent, data_result: DataResult)
             let serialized =
                 serde_json::to_string(&ApiNodeMessage::PlayerUpdate(Box::new(event))).ok()?;
 
+            plugins::dispatch_event(&serialized);
+
             player_event
                 .send_to_websocket(Message::Text(Utf8Bytes::from(serialized)))
                 .await;
@@ -204,7 +240,7 @@ async fn handle_player_event(player_event: PlayerEvent, data_result: DataResult)
             Some(())
         }
         Event::Track(event) => {
-            let DataResult::Track(_, track) = data_result else {
+            let DataResult::Track(state, track, uuid) = data_result else {
                 tracing::warn!("Expected DataResult::Track but got a different thing");
                 return None;
             };
@@ -229,29 +265,35 @@ async fn handle_player_event(player_event: PlayerEvent, data_result: DataResult)
                     Some(())
                 }
                 TrackEvent::End => {
-                    player_event
-                        .active
-                        .upgrade()?
-                        .swap(false, Ordering::Relaxed);
+                    let reason = player_event.end_reason(&uuid, &state.playing);
 
-                    let arc = player_event.data.upgrade()?;
+                    metrics::track_end(reason.as_str());
 
-                    let mut data = arc.lock().await;
+                    // a replaced track ends after the next one started, which owns the player state now
+                    if reason != ApiTrackEndReason::Replaced {
+                        player_event
+                            .active
+                            .upgrade()?
+                            .swap(false, Ordering::Relaxed);
 
-                    data.track.take();
-                    data.paused = false;
-                    data.state.position = 0;
+                        let arc = player_event.data.upgrade()?;
 
-                    drop(data);
-                    drop(arc);
+                        let mut data = arc.lock().await;
+
+                        data.track.take();
+                        data.paused = false;
+                        data.state.position = 0;
 
-                    player_event.stop(false).await;
+                        drop(data);
+                        drop(arc);
+
+                        player_event.stop(false).await;
+                    }
 
                     let event = ApiTrackEnd {
                         guild_id: player_event.guild_id.0.get(),
                         track: track.as_ref().clone(),
-                        // todo: reflect reason for this end
-                        reason: String::from("finished"),
+                        reason,
                     };
 
                     let serialized = serde_json::to_string(&ApiNodeMessage::Event(Box::new(
@@ -259,6 +301,8 @@ async fn handle_player_event(player_event: PlayerEvent, data_result: DataResult)
                     )))
                     .ok()?;
 
+                    plugins::dispatch_event(&serialized);
+
                     player_event
                         .send_to_websocket(Message::Text(Utf8Bytes::from(serialized)))
                         .await;
@@ -284,6 +328,8 @@ async fn handle_player_event(player_event: PlayerEvent, data_result: DataResult)
                     drop(data);
                     drop(arc);
 
+                    metrics::track_start(&track.info.source_name);
+
                     let event = ApiTrackStart {
                         guild_id: player_event.guild_id.0.get(),
                         track: track.as_ref().clone(),
@@ -294,6 +340,8 @@ async fn handle_player_event(player_event: PlayerEvent, data_result: DataResult)
                     )))
                     .ok()?;
 
+                    plugins::dispatch_event(&serialized);
+
                     player_event
                         .send_to_websocket(Message::Text(Utf8Bytes::from(serialized)))
                         .await;
@@ -329,6 +377,8 @@ async fn handle_player_event(player_event: PlayerEvent, data_result: DataResult)
             )))
             .ok()?;
 
+            plugins::dispatch_event(&serialized);
+
             player_event
                 .send_to_websocket(Message::Text(Utf8Bytes::from(serialized)))
                 .await;
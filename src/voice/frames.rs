@@ -0,0 +1,83 @@
+use crate::Clients;
+use crate::models::ApiFrameStats;
+use dashmap::DashMap;
+use songbird::tracks::PlayMode;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+// songbird sends a frame every 20ms
+const FRAME_DURATION_MS: u128 = 20;
+
+// track positions from the previous sample, keyed by track uuid
+static LAST_POSITIONS: LazyLock<DashMap<Uuid, Duration>> = LazyLock::new(DashMap::new);
+
+/**
+ * Frame stats per playing player and minute, like Lavalink reports them. Frames are counted from how far each track moved since the last sample.
+ * Songbird doesn't report the silent frames it fills in, so nulled stays 0 and missing frames end up in the deficit
+ */
+pub async fn collect_frame_stats(elapsed: Duration) -> Option<ApiFrameStats> {
+    // only the handle mutexes are cloned, dropping a player clone would tear down its driver
+    let handles = Clients
+        .iter()
+        .flat_map(|client| {
+            client
+                .player_manager
+                .players
+                .iter()
+                .map(|player| player.handle.clone())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let expected = elapsed.as_millis() / FRAME_DURATION_MS;
+
+    let mut seen = HashSet::new();
+    let mut players = 0u128;
+    let mut sent = 0u128;
+
+    for handle in handles {
+        let Some(handle) = handle.lock().await.clone() else {
+            continue;
+        };
+
+        let Ok(state) = handle.get_info().await else {
+            continue;
+        };
+
+        if !matches!(state.playing, PlayMode::Play) {
+            continue;
+        }
+
+        seen.insert(handle.uuid());
+
+        // the first sample of a track has nothing to compare against
+        let Some(previous) = LAST_POSITIONS.insert(handle.uuid(), state.position) else {
+            continue;
+        };
+
+        players += 1;
+
+        // seeks move the position by more than what was played, so it is capped
+        sent +=
+            (state.position.saturating_sub(previous).as_millis() / FRAME_DURATION_MS).min(expected);
+    }
+
+    LAST_POSITIONS.retain(|uuid, _| seen.contains(uuid));
+
+    if players == 0 || expected == 0 {
+        return None;
+    }
+
+    let per_minute = |frames: u128| frames * 60_000 / elapsed.as_millis() / players;
+
+    let sent = per_minute(sent);
+    let expected = per_minute(expected * players);
+
+    Some(ApiFrameStats {
+        sent: sent as u64,
+        nulled: 0,
+        deficit: expected.saturating_sub(sent) as i32,
+    })
+}
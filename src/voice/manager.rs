@@ -1,6 +1,7 @@
 use super::player::Player;
 use crate::models::ApiVoiceData;
 use crate::util::errors::PlayerManagerError;
+use crate::util::tenant::Tenant;
 use axum::extract::ws::Message;
 use dashmap::DashMap;
 use dashmap::mapref::one::Ref;
@@ -16,17 +17,19 @@ pub enum CleanerSender {
 
 pub struct PlayerManager {
     pub user_id: UserId,
+    pub tenant: Arc<Tenant>,
     pub players: Arc<DashMap<GuildId, Player>>,
     cleaner: Sender<CleanerSender>,
     websocket: WeakSender<Message>,
 }
 
 impl PlayerManager {
-    pub fn new(websocket: WeakSender<Message>, user_id: UserId) -> Self {
+    pub fn new(websocket: WeakSender<Message>, user_id: UserId, tenant: Arc<Tenant>) -> Self {
         let (cleaner, listener) = unbounded::<CleanerSender>();
 
         let manager = Self {
             user_id,
+            tenant,
             cleaner,
             websocket,
             players: Arc::new(DashMap::new()),
@@ -58,9 +61,17 @@ impl PlayerManager {
         config: Option<Config>,
     ) -> Result<Ref<'_, GuildId, Player>, PlayerManagerError> {
         let Some(player) = self.players.get(&guild_id) else {
+            let slot = self.tenant.acquire_player().ok_or_else(|| {
+                PlayerManagerError::PlayerLimitReached(
+                    self.tenant.name.clone(),
+                    self.tenant.max_players.unwrap_or(u32::MAX),
+                )
+            })?;
+
             let player = Player::new(
                 self.websocket.clone(),
                 self.cleaner.downgrade(),
+                slot,
                 config,
                 self.user_id,
                 guild_id,
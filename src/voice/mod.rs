@@ -1,3 +1,4 @@
 pub mod events;
+pub mod frames;
 pub mod manager;
 pub mod player;
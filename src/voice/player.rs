@@ -1,10 +1,13 @@
 use super::{events::PlayerEvent, manager::CleanerSender};
 use crate::{
-    Config, Scheduler,
-    models::{ApiPlayer, ApiPlayerState, ApiTrack, ApiVoiceData, Empty},
-    util::{decoder::decode_base64, errors::PlayerError},
+    Clients, Config, Scheduler,
+    models::{
+        ApiPlayer, ApiPlayerState, ApiTrack, ApiTrackEndReason, ApiVoiceData, Empty, PluginInfo,
+    },
+    util::{decoder::decode_base64, errors::PlayerError, tenant::PlayerSlot},
 };
 use axum::extract::ws::Message;
+use dashmap::DashMap;
 use flume::WeakSender;
 use songbird::{
     Config as SongbirdConfig, ConnectionInfo, CoreEvent, Driver, Event, TrackEvent,
@@ -17,6 +20,7 @@ use std::{
     time::{Duration, Instant},
 };
 use tokio::{sync::Mutex, task};
+use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct Player {
@@ -28,12 +32,18 @@ pub struct Player {
     pub cleaner: WeakSender<CleanerSender>,
     pub driver: Arc<Mutex<Option<Driver>>>,
     pub handle: Arc<Mutex<Option<TrackHandle>>>,
+    pub slot: Arc<PlayerSlot>,
+    /**
+     * Why a track was ended by us, keyed by track uuid. Tracks that aren't in here ended on their own
+     */
+    pub end_reasons: Arc<DashMap<Uuid, ApiTrackEndReason>>,
 }
 
 impl Player {
     pub async fn new(
         websocket: WeakSender<Message>,
         cleaner: WeakSender<CleanerSender>,
+        slot: PlayerSlot,
         config: Option<SongbirdConfig>,
         user_id: UserId,
         guild_id: GuildId,
@@ -67,6 +77,8 @@ impl Player {
             cleaner,
             driver: Arc::new(Mutex::new(None)),
             handle: Arc::new(Mutex::new(None)),
+            slot: Arc::new(slot),
+            end_reasons: Arc::new(DashMap::new()),
         };
 
         player.connect(&server_update, config).await?;
@@ -86,6 +98,7 @@ impl Player {
         Some(state.clone())
     }
 
+    #[tracing::instrument(skip_all, fields(guild_id = %self.guild_id, user_id = %self.user_id))]
     pub async fn connect(
         &self,
         server_update: &ApiVoiceData,
@@ -109,18 +122,9 @@ impl Player {
 
             driver.set_bitrate(Bitrate::Max);
 
-            driver.add_global_event(
-                Event::Core(CoreEvent::DriverDisconnect),
-                PlayerEvent::new(Event::Core(CoreEvent::DriverDisconnect), self),
-            );
-
-            driver.add_global_event(
-                Event::Periodic(
-                    Duration::from_secs(Config.player_update_secs.unwrap_or(5) as u64),
-                    None,
-                ),
-                PlayerEvent::new(Event::Periodic(Duration::from_secs(10), None), self),
-            );
+            for (event, action) in self.global_events() {
+                driver.add_global_event(event, action);
+            }
 
             let _ = guard.insert(driver);
 
@@ -143,7 +147,29 @@ impl Player {
         Ok(())
     }
 
+    /**
+     * Driver wide events, the player update interval is read from the current config
+     */
+    fn global_events(&self) -> [(Event, PlayerEvent); 2] {
+        [
+            (
+                Event::Core(CoreEvent::DriverDisconnect),
+                PlayerEvent::new(Event::Core(CoreEvent::DriverDisconnect), self),
+            ),
+            (
+                Event::Periodic(
+                    Duration::from_secs(Config.load().player_update_secs.unwrap_or(5) as u64),
+                    None,
+                ),
+                PlayerEvent::new(Event::Periodic(Duration::from_secs(10), None), self),
+            ),
+        ]
+    }
+
+    #[tracing::instrument(skip_all, fields(guild_id = %self.guild_id, user_id = %self.user_id))]
     pub async fn disconnect(&self) {
+        self.mark_end(ApiTrackEndReason::Cleanup).await;
+
         let mut guard = self.driver.lock().await;
 
         if let Some(driver) = guard.take().as_mut() {
@@ -158,13 +184,18 @@ impl Player {
         guard.state.connected = false;
     }
 
+    #[tracing::instrument(skip_all, fields(guild_id = %self.guild_id, user_id = %self.user_id))]
     pub async fn play(&self, encoded: String) -> Result<(), PlayerError> {
         let info = decode_base64(&encoded)?;
 
+        if !self.slot.tenant.is_source_allowed(&info.source_name) {
+            return Err(PlayerError::SourceNotAllowed(info.source_name));
+        }
+
         let api_track = ApiTrack {
             encoded,
             info,
-            plugin_info: Empty,
+            plugin_info: PluginInfo::new(),
         };
 
         let mut track = api_track.make_playable().await?;
@@ -177,7 +208,7 @@ impl Player {
 
         drop(guard);
 
-        // todo: before sending the new track, we may want to send a replaced notification from here before playing the new track
+        self.mark_end(ApiTrackEndReason::Replaced).await;
 
         let mut guard = self.driver.lock().await;
 
@@ -221,9 +252,21 @@ impl Player {
             return;
         };
 
+        self.end_reasons
+            .insert(handle.uuid(), ApiTrackEndReason::Stopped);
+
         handle.stop().ok();
     }
 
+    /**
+     * Records why the current track is about to end, so its end event reports it
+     */
+    async fn mark_end(&self, reason: ApiTrackEndReason) {
+        if let Some(handle) = self.handle.lock().await.as_ref() {
+            self.end_reasons.insert(handle.uuid(), reason);
+        }
+    }
+
     pub async fn seek(&self, position: u32) {
         let guard = self.data.lock().await;
 
@@ -310,6 +353,38 @@ impl Player {
     }
 }
 
+/**
+ * Registers the global events of every connected player again, so a changed player update interval applies to them as well
+ */
+pub async fn refresh_global_events() {
+    // the events are built first since the client and player maps can't be held across the driver locks
+    let drivers = Clients
+        .iter()
+        .flat_map(|client| {
+            client
+                .player_manager
+                .players
+                .iter()
+                .map(|player| (player.driver.clone(), player.global_events()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    for (driver, events) in drivers {
+        let mut guard = driver.lock().await;
+
+        let Some(driver) = guard.as_mut() else {
+            continue;
+        };
+
+        driver.remove_all_global_events();
+
+        for (event, action) in events {
+            driver.add_global_event(event, action);
+        }
+    }
+}
+
 impl Drop for Player {
     fn drop(&mut self) {
         let arc_driver = self.driver.clone();
@@ -322,8 +397,11 @@ impl Drop for Player {
 
         let arc_handle = self.handle.clone();
 
+        let end_reasons = self.end_reasons.clone();
+
         task::block_in_place(move || {
             if let Some(handle) = arc_handle.blocking_lock().take() {
+                end_reasons.insert(handle.uuid(), ApiTrackEndReason::Cleanup);
                 drop(handle);
             }
         });
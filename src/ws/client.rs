@@ -1,6 +1,8 @@
-use crate::Clients;
 use crate::models::{ApiNodeMessage, ApiReady};
+use crate::util::metrics;
+use crate::util::tenant::Tenant;
 use crate::voice::manager::PlayerManager;
+use crate::{Clients, UserSessions};
 use axum::Error;
 use axum::body::Bytes;
 use axum::extract::ConnectInfo;
@@ -22,11 +24,13 @@ pub struct WebsocketRequestData {
     pub user_agent: String,
     pub user_id: UserId,
     pub session_id: Option<u128>,
+    pub tenant: Arc<Tenant>,
 }
 
 pub struct WebsocketClient {
     pub user_id: UserId,
     pub session_id: u128,
+    pub tenant: Arc<Tenant>,
     pub player_manager: PlayerManager,
     pub resume: bool,
     pub timeout: u16,
@@ -36,16 +40,18 @@ pub struct WebsocketClient {
 }
 
 impl WebsocketClient {
-    pub fn new(user_id: UserId) -> Self {
+    pub fn new(user_id: UserId, tenant: Arc<Tenant>) -> Self {
         let session_id = Uuid::new_v4().as_u128();
         let (message_sender, message_receiver) = unbounded::<Message>();
-        let player_manager = PlayerManager::new(message_sender.downgrade(), user_id);
+        let player_manager =
+            PlayerManager::new(message_sender.downgrade(), user_id, tenant.clone());
         let resume = false;
         let timeout = 30;
 
         Self {
             user_id,
             session_id,
+            tenant,
             player_manager,
             resume,
             timeout,
@@ -81,6 +87,8 @@ impl WebsocketClient {
 
             resumed = true;
 
+            metrics::websocket_resume();
+
             tracing::info!(
                 "Websocket Connection with [SessionId: {}] resumed! [Replayed Messages: {}]",
                 self.session_id,
@@ -91,8 +99,6 @@ impl WebsocketClient {
 
             self.player_manager.disconnect_all();
 
-            self.session_id = Uuid::new_v4().as_u128();
-
             tracing::info!(
                 "Websocket Connection with [SessionId: {}] identified! [Dropped Messages: {}]",
                 self.session_id,
@@ -106,6 +112,7 @@ impl WebsocketClient {
         let dropped = ptr.clone();
         let message_sender = self.message_sender.clone();
         let user_id = self.user_id.to_owned();
+        let session_id = self.session_id;
         let players = self.player_manager.players.clone();
 
         let timeout = self.timeout;
@@ -144,9 +151,13 @@ impl WebsocketClient {
 
             players.clear();
 
-            Clients.remove(&user_id);
+            remove_session(user_id, session_id);
 
-            tracing::info!("Cleaned up websocket client for [UserId {}]", user_id);
+            tracing::info!(
+                "Cleaned up websocket client for [SessionId: {}] [UserId {}]",
+                session_id,
+                user_id
+            );
         });
 
         self.handles.push(receive_handle);
@@ -238,28 +249,69 @@ impl WebsocketClient {
     }
 }
 
+fn remove_session(user_id: UserId, session_id: u128) {
+    Clients.remove(&session_id);
+
+    if let Some(sessions) = UserSessions.get(&user_id) {
+        sessions.remove(&session_id);
+    }
+
+    UserSessions.remove_if(&user_id, |_, sessions| sessions.is_empty());
+}
+
 pub async fn handle_websocket_upgrade_request(
     socket: WebSocket,
     data: WebsocketRequestData,
     addr: ConnectInfo<SocketAddr>,
 ) {
-    let Some(mut client) = Clients.get_mut(&data.user_id) else {
-        let client = WebsocketClient::new(data.user_id);
-
-        Clients.insert(data.user_id, client);
-
-        return Box::pin(handle_websocket_upgrade_request(socket, data, addr)).await;
+    // only sessions owned by this user and tenant with resuming enabled can be taken over, everything else gets a new session
+    let resumable = data.session_id.filter(|session_id| {
+        Clients.get(session_id).is_some_and(|client| {
+            client.user_id == data.user_id && client.tenant.owns(&data.tenant) && client.resume
+        })
+    });
+
+    let session_id = resumable.unwrap_or_else(|| {
+        let client = WebsocketClient::new(data.user_id, data.tenant.clone());
+        let session_id = client.session_id;
+
+        Clients.insert(session_id, client);
+
+        UserSessions
+            .entry(data.user_id)
+            .or_default()
+            .insert(session_id);
+
+        session_id
+    });
+
+    let sessions = UserSessions
+        .get(&data.user_id)
+        .map(|sessions| sessions.len())
+        .unwrap_or(0);
+
+    let Some(mut client) = Clients.get_mut(&session_id) else {
+        tracing::warn!(
+            "Connection was not handled properly from: {}. [SessionId: {}] [UserId: {}] [UserAgent: {}] [Error: Session was removed before connecting]",
+            addr.ip(),
+            session_id,
+            data.user_id,
+            data.user_agent,
+        );
+        return;
     };
 
     match client.connect(socket, data.session_id).await {
         Ok(resumed) => {
             tracing::info!(
-                "Handled connection from: {}. [SessionId: {}] [UserId: {}] [UserAgent: {}] [Resume: {}]",
+                "Handled connection from: {}. [SessionId: {}] [UserId: {}] [UserAgent: {}] [Tenant: {}] [Resume: {}] [Sessions: {}]",
                 addr.ip(),
                 client.session_id,
                 data.user_id,
                 data.user_agent,
-                resumed
+                data.tenant.name,
+                resumed,
+                sessions
             );
         }
         Err(error) => {
@@ -0,0 +1,90 @@
+// A plugin used by the tests, built with rustc as a cdylib without any dependencies.
+// Compiling with `--cfg abi_mismatch` makes it report an abi version the node doesn't know
+
+use std::ffi::{CStr, CString, c_char};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static LIVE_STRINGS: AtomicUsize = AtomicUsize::new(0);
+
+const MANIFEST: &str = r#"{
+    "name": "fixture",
+    "version": "1.2.3",
+    "sources": [
+        { "name": "Fixture", "searchPrefixes": ["fxsearch:"], "urlPatterns": ["^https://fixture\\.test/"] }
+    ],
+    "routes": [{ "method": "post", "path": "/echo" }],
+    "pluginInfoFields": ["mood"]
+}"#;
+
+const TRACK: &str = r#"{
+    "encoded": "",
+    "info": {
+        "identifier": "fx-1",
+        "isSeekable": true,
+        "author": "Fixture",
+        "length": 1000,
+        "isStream": false,
+        "position": 0,
+        "title": "From a plugin",
+        "uri": "https://fixture.test/fx-1",
+        "artworkUrl": null,
+        "isrc": null,
+        "sourceName": "whatever the plugin says"
+    },
+    "pluginInfo": { "mood": "calm" }
+}"#;
+
+fn give(string: String) -> *mut c_char {
+    LIVE_STRINGS.fetch_add(1, Ordering::SeqCst);
+
+    CString::new(string).unwrap().into_raw()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn ibuki_plugin_abi_version() -> u32 {
+    if cfg!(abi_mismatch) { 99 } else { 1 }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn ibuki_plugin_manifest() -> *mut c_char {
+    give(MANIFEST.to_string())
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ibuki_plugin_call(
+    method: *const c_char,
+    payload: *const c_char,
+) -> *mut c_char {
+    let method = unsafe { CStr::from_ptr(method) }.to_str().unwrap();
+    let payload = unsafe { CStr::from_ptr(payload) }.to_str().unwrap();
+
+    let response = match method {
+        "resolve" if payload.contains(r#""type":"search""#) => {
+            format!(r#"{{ "ok": {{ "loadType": "search", "data": [{TRACK}, {TRACK}] }} }}"#)
+        }
+        "resolve" => format!(r#"{{ "ok": {{ "loadType": "track", "data": {TRACK} }} }}"#),
+        "route" => format!(r#"{{ "ok": {{ "status": 201, "body": {{ "received": {payload} }} }} }}"#),
+        "echo" => format!(r#"{{ "ok": {payload} }}"#),
+        "fail" => String::from(r#"{ "error": "nothing here" }"#),
+        "garbage" => String::from("not json"),
+        "null" => return std::ptr::null_mut(),
+        _ => String::from(r#"{ "ok": null }"#),
+    };
+
+    give(response)
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ibuki_plugin_free(ptr: *mut c_char) {
+    LIVE_STRINGS.fetch_sub(1, Ordering::SeqCst);
+
+    drop(unsafe { CString::from_raw(ptr) });
+}
+
+/**
+ * Not part of the abi, lets the tests check that every string handed out was freed
+ */
+#[unsafe(no_mangle)]
+pub extern "C" fn fixture_live_strings() -> usize {
+    LIVE_STRINGS.load(Ordering::SeqCst)
+}